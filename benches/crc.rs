@@ -0,0 +1,25 @@
+// Compare the slicing-by-8 crc() against the RFC 1662 bytewise loop, over
+// frame sizes seen on the radar link.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use serial_link::crc::{crc, crc_bytewise, INITIAL_FCS};
+
+fn bench_crc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("crc");
+    for size in [16usize, 256, 1500, 65536] {
+        let data: Vec<u8> = (0..size).map(|n| n as u8).collect();
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("bytewise", size), &data, |b, data| {
+            b.iter(|| crc_bytewise(INITIAL_FCS, black_box(data)))
+        });
+        group.bench_with_input(BenchmarkId::new("slice8", size), &data, |b, data| {
+            b.iter(|| crc(INITIAL_FCS, black_box(data)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_crc);
+criterion_main!(benches);
@@ -1,9 +1,31 @@
 fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(feature = "protobuf")]
     protobuf_codegen::Codegen::new()
         .cargo_out_dir("protos")
         .include("src")
         .input("src/protos/example.proto")
         .run_from_script();
 
-    cc::Build::new().file("src/hdlc.c").compile("hdlc");
+    #[cfg(feature = "c-hdlc")]
+    build_hdlc();
+}
+
+#[cfg(feature = "c-hdlc")]
+fn build_hdlc() {
+    println!("cargo:rerun-if-changed=src/hdlc.c");
+    println!("cargo:rerun-if-changed=src/hdlc.h");
+    let mut build = cc::Build::new();
+    build.file("src/hdlc.c");
+
+    // cargo fuzz only instruments the Rust code, the C code needs the same
+    // sanitizer and coverage flags to be checked and explored.
+    if let Ok(sanitizers) = std::env::var("CARGO_CFG_SANITIZE") {
+        for sanitizer in sanitizers.split(',') {
+            build.flag(format!("-fsanitize={sanitizer}"));
+        }
+        build.flag_if_supported("-fsanitize-coverage=inline-8bit-counters,pc-table,trace-cmp");
+    }
+    build.compile("hdlc");
 }
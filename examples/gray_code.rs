@@ -0,0 +1,4 @@
+fn main() {
+    // An exerise in single track gray codes.
+    serial_link::gray_code::single_track_gray_code();
+}
@@ -0,0 +1,29 @@
+// Round trips a message holding every byte value through the HDLC Encoder and
+// Framer.
+
+use serial_link::hdlc::{Encoder, Framer, FramerEvent};
+
+fn main() {
+    // Build a "message" containing all possible byte values.
+    let mut data: Vec<u8> = vec![];
+    for byte in 0x00u8..=0xFFu8 {
+        data.push(byte);
+    }
+    println!("Data in: {:x?}", data);
+
+    let mut encoder = Encoder::new();
+    let encoded = encoder.encode(&data);
+    println!("Encoded: {:x?}", encoded);
+
+    let mut framer = Framer::new();
+    for byte in encoded {
+        match framer.find_frame(byte) {
+            Some(FramerEvent::Frame(frame)) => {
+                println!("Data out: {:x?}", &frame);
+                assert_eq!(frame, data);
+            }
+            Some(event) => panic!("Framer failed with: {:?}", event),
+            None => {}
+        }
+    }
+}
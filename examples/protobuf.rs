@@ -0,0 +1,3 @@
+fn main() {
+    serial_link::protobuf_experiment::protobuf_experiment();
+}
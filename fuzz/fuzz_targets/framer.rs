@@ -0,0 +1,56 @@
+// Feeds arbitrary bytes to the Framer, both a byte at a time and as whole
+// frames, with link options picked by the first byte.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use serial_link::hdlc;
+use serial_link::hdlc::{Accm, FcsWidth, Framer, FramerEvent, FLAG};
+
+fn link_framer(options: u8) -> Framer {
+    let accm = match options & 0x01 {
+        0 => Accm::none(),
+        _ => Accm::default(),
+    };
+    let fcs_width = match options & 0x02 {
+        0 => FcsWidth::Fcs16,
+        _ => FcsWidth::Fcs32,
+    };
+    // Small limits so that overflows are reached too.
+    let max_frame_len = match options >> 4 {
+        0 => hdlc::DEFAULT_MAX_FRAME_LEN,
+        n => n as usize * 4,
+    };
+    Framer::new()
+        .with_accm(accm)
+        .with_pfc(options & 0x04 != 0)
+        .with_fcs_width(fcs_width)
+        .with_max_frame_len(max_frame_len)
+}
+
+fn check_event(framer: &Framer, event: &FramerEvent) {
+    if let FramerEvent::Frame(frame) = event {
+        // PFC may add back one byte of Protocol field.
+        assert!(frame.len() <= framer.max_frame_len() + 1);
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((options, stream)) = data.split_first() else {
+        return;
+    };
+
+    let mut framer = link_framer(*options);
+    for byte in stream {
+        if let Some(event) = framer.find_frame(*byte) {
+            check_event(&framer, &event);
+        }
+    }
+
+    let mut framer = link_framer(*options);
+    for raw in stream.split(|byte| *byte == FLAG) {
+        let event = framer.decode_frame(raw);
+        check_event(&framer, &event);
+    }
+});
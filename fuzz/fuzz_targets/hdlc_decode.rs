@@ -0,0 +1,20 @@
+// Passes arbitrary frames to hdlc_decode in hdlc.c, with packet buffers both
+// large enough and too small.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use serial_link::hdlc_ffi::{hdlc_decode_ffi, HdlcError};
+
+fuzz_target!(|data: &[u8]| {
+    for pktsize in [data.len(), data.len() / 2, 3, 0] {
+        let mut packet = vec![0u8; pktsize];
+        match hdlc_decode_ffi(data, &mut packet) {
+            Ok(size) => assert!(size <= pktsize && size < data.len()),
+            Err(HdlcError::NoFrameFound) => panic!("hdlc_decode does not look for frames"),
+            Err(e @ HdlcError::Unknown(_)) => panic!("hdlc_decode failed with: {e}"),
+            Err(_) => {}
+        }
+    }
+});
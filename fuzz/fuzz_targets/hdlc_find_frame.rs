@@ -0,0 +1,26 @@
+// Looks for frames in arbitrary buffers with hdlc_find_frame in hdlc.c.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use serial_link::hdlc::FLAG;
+use serial_link::hdlc_ffi::{hdlc_find_frame_ffi, hdlc_frames_ffi};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(frame) = hdlc_find_frame_ffi(data) {
+        assert!(!frame.is_empty());
+    }
+
+    let mut frames = hdlc_frames_ffi(data);
+    let mut end = 0;
+    for (offset, frame) in frames.by_ref() {
+        // Frames are found in order, between Flag Sequences.
+        assert!(offset > end);
+        assert_eq!(data[offset - 1], FLAG);
+        assert_eq!(data[offset + frame.len()], FLAG);
+        assert!(!frame.contains(&FLAG));
+        end = offset + frame.len();
+    }
+    assert_eq!(data.len() - frames.remainder().len(), end);
+});
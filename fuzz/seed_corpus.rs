@@ -0,0 +1,74 @@
+// Builds a seed corpus for the fuzz targets from the radar capture, so fuzzing
+// starts from the frames our devices really send. Run from the fuzz directory:
+//
+//   cargo run --bin seed_corpus
+//
+// The capture carries no FCS, so its packets are also encoded again, each as
+// a frame of its own, to give seeds that get past the checksum.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use serial_link::hdlc::{Encoder, FLAG};
+use serial_link::serial_port_test::capture_packets;
+
+// Enough distinct frames to cover the message types, without slowing down
+// every fuzzing run.
+const MAX_SEEDS: usize = 64;
+
+fn write_seeds(target: &str, seeds: &[Vec<u8>]) -> std::io::Result<()> {
+    let dir = Path::new("corpus").join(target);
+    fs::create_dir_all(&dir)?;
+    for (n, seed) in seeds.iter().enumerate() {
+        fs::write(dir.join(format!("radar-{n:03}")), seed)?;
+    }
+    println!("{}: {} seeds", dir.display(), seeds.len());
+    Ok(())
+}
+
+fn main() -> std::io::Result<()> {
+    let capture: &[u8] = include!("../radar_capture.cap");
+
+    // Distinct frames without their Flag Sequences, the capture ends mid-frame.
+    let mut seen = HashSet::new();
+    let frames: Vec<&[u8]> = capture
+        .split(|byte| *byte == FLAG)
+        .skip(1)
+        .filter(|frame| !frame.is_empty() && seen.insert(frame.to_vec()))
+        .take(MAX_SEEDS)
+        .collect();
+
+    // The capture frames are escaped, encode their unescaped packets so as
+    // not to escape them twice. A fresh Encoder starts each with a Flag Sequence.
+    let mut seen = HashSet::new();
+    let encoded: Vec<Vec<u8>> = capture_packets()
+        .into_iter()
+        .filter(|packet| seen.insert(packet.clone()))
+        .take(MAX_SEEDS)
+        .map(|packet| Encoder::new().encode(&packet))
+        .collect();
+
+    let delimited: Vec<Vec<u8>> = frames
+        .iter()
+        .map(|frame| [&[FLAG], *frame, &[FLAG]].concat())
+        .collect();
+    let unflagged: Vec<Vec<u8>> = encoded
+        .iter()
+        .map(|frame| frame[1..frame.len() - 1].to_vec())
+        .collect();
+    // A leading options byte of 0 selects the default Framer.
+    let framer: Vec<Vec<u8>> = delimited
+        .iter()
+        .chain(encoded.iter())
+        .map(|frame| [&[0], frame.as_slice()].concat())
+        .collect();
+
+    write_seeds("framer", &framer)?;
+    write_seeds(
+        "hdlc_decode",
+        &[frames.iter().map(|frame| frame.to_vec()).collect(), unflagged].concat(),
+    )?;
+    write_seeds("hdlc_find_frame", &[delimited, encoded].concat())?;
+    Ok(())
+}
@@ -1,3 +1,5 @@
+use core::hash::Hasher;
+
 //
 // Lookup table used to calculate the CRC, as generated in RFC 1662.
 //
@@ -34,13 +36,591 @@ const CRC_TAB: &[u16] = &[
 // return   new CRC value.
 //
 pub fn crc(mut sum: u16, seq: &[u8]) -> u16 {
+    let mut chunks = seq.chunks_exact(8);
+    for chunk in &mut chunks {
+        sum = crc_slice8(sum, chunk);
+    }
+    crc_bytewise(sum, chunks.remainder())
+}
+
+//
+// Calculates a new CRC one byte at a time, as in RFC 1662.
+//
+pub fn crc_bytewise(mut sum: u16, seq: &[u8]) -> u16 {
     for byte in seq {
         sum = (sum >> 8) ^ CRC_TAB[((sum ^ (*byte as u16)) & 0xff) as usize];
     }
     sum
 }
 
+//
+// Lookup tables for slicing-by-8. CRC_TAB8[k][n] is the CRC of byte n
+// followed by k zero bytes, so CRC_TAB8[0] is CRC_TAB.
+//
+static CRC_TAB8: [[u16; 256]; 8] = crc_tab8();
+
+const fn crc_tab8() -> [[u16; 256]; 8] {
+    let mut tab = [[0u16; 256]; 8];
+    let mut n = 0;
+    while n < 256 {
+        tab[0][n] = CRC_TAB[n];
+        n += 1;
+    }
+    let mut k = 1;
+    while k < 8 {
+        let mut n = 0;
+        while n < 256 {
+            let prev = tab[k - 1][n];
+            tab[k][n] = (prev >> 8) ^ CRC_TAB[(prev & 0xff) as usize];
+            n += 1;
+        }
+        k += 1;
+    }
+    tab
+}
+
+//
+// Calculates a new CRC over 8 bytes of data with 8 independent table lookups.
+//
+fn crc_slice8(sum: u16, chunk: &[u8]) -> u16 {
+    let b0 = chunk[0] ^ (sum & 0xff) as u8;
+    let b1 = chunk[1] ^ (sum >> 8) as u8;
+    CRC_TAB8[7][b0 as usize]
+        ^ CRC_TAB8[6][b1 as usize]
+        ^ CRC_TAB8[5][chunk[2] as usize]
+        ^ CRC_TAB8[4][chunk[3] as usize]
+        ^ CRC_TAB8[3][chunk[4] as usize]
+        ^ CRC_TAB8[2][chunk[5] as usize]
+        ^ CRC_TAB8[1][chunk[6] as usize]
+        ^ CRC_TAB8[0][chunk[7] as usize]
+}
+
 //
 // Precalculated CRC for Address and Control fields of [0xff, 0x03];
 //
 pub const ADDRESS_CONTROL_CHECKSUM: u16 = 0x3de3;
+
+//
+// Initial CRC value, also used to complement the CRC before transmission.
+//
+pub const INITIAL_FCS: u16 = 0xffff;
+
+//
+// CRC of a received frame including its FCS field when the frame is intact.
+//
+pub const GOOD_FCS: u16 = 0xf0b8;
+
+//
+// Lookup table used to calculate the 32-bit CRC, as generated in RFC 1662
+// section C.3.
+//
+const CRC32_TAB: &[u32] = &[
+    0x00000000, 0x77073096, 0xee0e612c, 0x990951ba, 0x076dc419, 0x706af48f, 0xe963a535, 0x9e6495a3,
+    0x0edb8832, 0x79dcb8a4, 0xe0d5e91e, 0x97d2d988, 0x09b64c2b, 0x7eb17cbd, 0xe7b82d07, 0x90bf1d91,
+    0x1db71064, 0x6ab020f2, 0xf3b97148, 0x84be41de, 0x1adad47d, 0x6ddde4eb, 0xf4d4b551, 0x83d385c7,
+    0x136c9856, 0x646ba8c0, 0xfd62f97a, 0x8a65c9ec, 0x14015c4f, 0x63066cd9, 0xfa0f3d63, 0x8d080df5,
+    0x3b6e20c8, 0x4c69105e, 0xd56041e4, 0xa2677172, 0x3c03e4d1, 0x4b04d447, 0xd20d85fd, 0xa50ab56b,
+    0x35b5a8fa, 0x42b2986c, 0xdbbbc9d6, 0xacbcf940, 0x32d86ce3, 0x45df5c75, 0xdcd60dcf, 0xabd13d59,
+    0x26d930ac, 0x51de003a, 0xc8d75180, 0xbfd06116, 0x21b4f4b5, 0x56b3c423, 0xcfba9599, 0xb8bda50f,
+    0x2802b89e, 0x5f058808, 0xc60cd9b2, 0xb10be924, 0x2f6f7c87, 0x58684c11, 0xc1611dab, 0xb6662d3d,
+    0x76dc4190, 0x01db7106, 0x98d220bc, 0xefd5102a, 0x71b18589, 0x06b6b51f, 0x9fbfe4a5, 0xe8b8d433,
+    0x7807c9a2, 0x0f00f934, 0x9609a88e, 0xe10e9818, 0x7f6a0dbb, 0x086d3d2d, 0x91646c97, 0xe6635c01,
+    0x6b6b51f4, 0x1c6c6162, 0x856530d8, 0xf262004e, 0x6c0695ed, 0x1b01a57b, 0x8208f4c1, 0xf50fc457,
+    0x65b0d9c6, 0x12b7e950, 0x8bbeb8ea, 0xfcb9887c, 0x62dd1ddf, 0x15da2d49, 0x8cd37cf3, 0xfbd44c65,
+    0x4db26158, 0x3ab551ce, 0xa3bc0074, 0xd4bb30e2, 0x4adfa541, 0x3dd895d7, 0xa4d1c46d, 0xd3d6f4fb,
+    0x4369e96a, 0x346ed9fc, 0xad678846, 0xda60b8d0, 0x44042d73, 0x33031de5, 0xaa0a4c5f, 0xdd0d7cc9,
+    0x5005713c, 0x270241aa, 0xbe0b1010, 0xc90c2086, 0x5768b525, 0x206f85b3, 0xb966d409, 0xce61e49f,
+    0x5edef90e, 0x29d9c998, 0xb0d09822, 0xc7d7a8b4, 0x59b33d17, 0x2eb40d81, 0xb7bd5c3b, 0xc0ba6cad,
+    0xedb88320, 0x9abfb3b6, 0x03b6e20c, 0x74b1d29a, 0xead54739, 0x9dd277af, 0x04db2615, 0x73dc1683,
+    0xe3630b12, 0x94643b84, 0x0d6d6a3e, 0x7a6a5aa8, 0xe40ecf0b, 0x9309ff9d, 0x0a00ae27, 0x7d079eb1,
+    0xf00f9344, 0x8708a3d2, 0x1e01f268, 0x6906c2fe, 0xf762575d, 0x806567cb, 0x196c3671, 0x6e6b06e7,
+    0xfed41b76, 0x89d32be0, 0x10da7a5a, 0x67dd4acc, 0xf9b9df6f, 0x8ebeeff9, 0x17b7be43, 0x60b08ed5,
+    0xd6d6a3e8, 0xa1d1937e, 0x38d8c2c4, 0x4fdff252, 0xd1bb67f1, 0xa6bc5767, 0x3fb506dd, 0x48b2364b,
+    0xd80d2bda, 0xaf0a1b4c, 0x36034af6, 0x41047a60, 0xdf60efc3, 0xa867df55, 0x316e8eef, 0x4669be79,
+    0xcb61b38c, 0xbc66831a, 0x256fd2a0, 0x5268e236, 0xcc0c7795, 0xbb0b4703, 0x220216b9, 0x5505262f,
+    0xc5ba3bbe, 0xb2bd0b28, 0x2bb45a92, 0x5cb36a04, 0xc2d7ffa7, 0xb5d0cf31, 0x2cd99e8b, 0x5bdeae1d,
+    0x9b64c2b0, 0xec63f226, 0x756aa39c, 0x026d930a, 0x9c0906a9, 0xeb0e363f, 0x72076785, 0x05005713,
+    0x95bf4a82, 0xe2b87a14, 0x7bb12bae, 0x0cb61b38, 0x92d28e9b, 0xe5d5be0d, 0x7cdcefb7, 0x0bdbdf21,
+    0x86d3d2d4, 0xf1d4e242, 0x68ddb3f8, 0x1fda836e, 0x81be16cd, 0xf6b9265b, 0x6fb077e1, 0x18b74777,
+    0x88085ae6, 0xff0f6a70, 0x66063bca, 0x11010b5c, 0x8f659eff, 0xf862ae69, 0x616bffd3, 0x166ccf45,
+    0xa00ae278, 0xd70dd2ee, 0x4e048354, 0x3903b3c2, 0xa7672661, 0xd06016f7, 0x4969474d, 0x3e6e77db,
+    0xaed16a4a, 0xd9d65adc, 0x40df0b66, 0x37d83bf0, 0xa9bcae53, 0xdebb9ec5, 0x47b2cf7f, 0x30b5ffe9,
+    0xbdbdf21c, 0xcabac28a, 0x53b39330, 0x24b4a3a6, 0xbad03605, 0xcdd70693, 0x54de5729, 0x23d967bf,
+    0xb3667a2e, 0xc4614ab8, 0x5d681b02, 0x2a6f2b94, 0xb40bbe37, 0xc30c8ea1, 0x5a05df1b, 0x2d02ef8d,
+];
+
+//
+// Calculates a new 32-bit CRC from the current CRC and new data.
+//
+// sum      Current CRC value.
+// seq      Array of new data.
+// return   new CRC value.
+//
+pub fn crc32(mut sum: u32, seq: &[u8]) -> u32 {
+    for byte in seq {
+        sum = (sum >> 8) ^ CRC32_TAB[((sum ^ (*byte as u32)) & 0xff) as usize];
+    }
+    sum
+}
+
+//
+// Precalculated 32-bit CRC for Address and Control fields of [0xff, 0x03];
+//
+pub const ADDRESS_CONTROL_CHECKSUM32: u32 = 0xb40b41c8;
+
+//
+// Initial 32-bit CRC value, also used to complement the CRC before transmission.
+//
+pub const INITIAL_FCS32: u32 = 0xffffffff;
+
+//
+// 32-bit CRC of a received frame including its FCS field when the frame is intact.
+//
+pub const GOOD_FCS32: u32 = 0xdebb20e3;
+
+//
+// A running 16-bit FCS, so callers need not know the initial value or the
+// good FCS residue.
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc16 {
+    sum: u16,
+}
+
+impl Crc16 {
+    pub fn new() -> Self {
+        Crc16 { sum: INITIAL_FCS }
+    }
+
+    //
+    // Starts as if the Address and Control fields had already been added.
+    //
+    pub fn with_address_control() -> Self {
+        Crc16 {
+            sum: ADDRESS_CONTROL_CHECKSUM,
+        }
+    }
+
+    pub fn update(&mut self, seq: &[u8]) {
+        self.sum = crc(self.sum, seq);
+    }
+
+    //
+    // The FCS to send after the data, least significant byte first.
+    //
+    pub fn finalize(&self) -> u16 {
+        self.sum ^ INITIAL_FCS
+    }
+
+    //
+    // Whether the data, including a received FCS field, is intact. That is
+    // the CRC has the good FCS residue.
+    //
+    pub fn verify(&self) -> bool {
+        self.sum == GOOD_FCS
+    }
+}
+
+impl Default for Crc16 {
+    fn default() -> Self {
+        Crc16::new()
+    }
+}
+
+impl Hasher for Crc16 {
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        self.finalize() as u64
+    }
+}
+
+//
+// A running 32-bit FCS, as Crc16.
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc32 {
+    sum: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Crc32 { sum: INITIAL_FCS32 }
+    }
+
+    pub fn with_address_control() -> Self {
+        Crc32 {
+            sum: ADDRESS_CONTROL_CHECKSUM32,
+        }
+    }
+
+    pub fn update(&mut self, seq: &[u8]) {
+        self.sum = crc32(self.sum, seq);
+    }
+
+    pub fn finalize(&self) -> u32 {
+        self.sum ^ INITIAL_FCS32
+    }
+
+    pub fn verify(&self) -> bool {
+        self.sum == GOOD_FCS32
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32::new()
+    }
+}
+
+impl Hasher for Crc32 {
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        self.finalize() as u64
+    }
+}
+
+//
+// Parameters of a CRC algorithm in the Rocksoft model, as described in
+// "A Painless Guide to CRC Error Detection Algorithms" by Ross N. Williams.
+//
+// width    Width of the CRC in bits, 1 to 32.
+// poly     Generator polynomial, without its top bit.
+// init     Initial register value.
+// refin    Whether input bytes are reflected, i.e. processed LSB first.
+// refout   Whether the register is reflected before the final XOR.
+// xorout   Value XORed into the register to give the CRC.
+// check    The CRC of the ASCII string "123456789".
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrcParams {
+    pub width: u8,
+    pub poly: u32,
+    pub init: u32,
+    pub refin: bool,
+    pub refout: bool,
+    pub xorout: u32,
+    pub check: u32,
+}
+
+//
+// A CRC algorithm with its lookup table, built at compile time when used in
+// a const or static.
+//
+// Reflected algorithms keep the register right aligned, others keep it left
+// aligned in the 32 bits so that every width shares the same byte loop.
+//
+pub struct Crc {
+    params: CrcParams,
+    table: [u32; 256],
+}
+
+const fn reflect(mut value: u32, width: u8) -> u32 {
+    let mut reflected = 0;
+    let mut bit = 0;
+    while bit < width {
+        reflected = (reflected << 1) | (value & 1);
+        value >>= 1;
+        bit += 1;
+    }
+    reflected
+}
+
+const fn mask(width: u8) -> u32 {
+    u32::MAX >> (32 - width)
+}
+
+impl Crc {
+    // Panics unless the width is 1 to 32 bits, which fails the build when
+    // the Crc is a const or static.
+    pub const fn new(params: CrcParams) -> Self {
+        assert!(
+            params.width >= 1 && params.width <= 32,
+            "CRC width must be 1 to 32 bits"
+        );
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut sum;
+            let mut bit = 0;
+            if params.refin {
+                let poly = reflect(params.poly, params.width);
+                sum = i as u32;
+                while bit < 8 {
+                    sum = if sum & 1 != 0 {
+                        (sum >> 1) ^ poly
+                    } else {
+                        sum >> 1
+                    };
+                    bit += 1;
+                }
+            } else {
+                let poly = params.poly << (32 - params.width);
+                sum = (i as u32) << 24;
+                while bit < 8 {
+                    sum = if sum & 0x80000000 != 0 {
+                        (sum << 1) ^ poly
+                    } else {
+                        sum << 1
+                    };
+                    bit += 1;
+                }
+            }
+            table[i] = sum;
+            i += 1;
+        }
+        Crc { params, table }
+    }
+
+    pub fn params(&self) -> &CrcParams {
+        &self.params
+    }
+
+    //
+    // The register value to start a calculation with.
+    //
+    pub fn init(&self) -> u32 {
+        if self.params.refin {
+            reflect(self.params.init, self.params.width)
+        } else {
+            self.params.init << (32 - self.params.width)
+        }
+    }
+
+    //
+    // Calculates a new register value from the current value and new data.
+    //
+    pub fn update(&self, mut sum: u32, seq: &[u8]) -> u32 {
+        if self.params.refin {
+            for byte in seq {
+                sum = (sum >> 8) ^ self.table[((sum ^ (*byte as u32)) & 0xff) as usize];
+            }
+        } else {
+            for byte in seq {
+                sum = (sum << 8) ^ self.table[((sum >> 24) ^ (*byte as u32)) as usize];
+            }
+        }
+        sum
+    }
+
+    //
+    // Turns a register value into the CRC.
+    //
+    pub fn finalize(&self, mut sum: u32) -> u32 {
+        let width = self.params.width;
+        if !self.params.refin {
+            sum >>= 32 - width;
+        }
+        if self.params.refin != self.params.refout {
+            sum = reflect(sum, width);
+        }
+        (sum ^ self.params.xorout) & mask(width)
+    }
+
+    pub fn checksum(&self, seq: &[u8]) -> u32 {
+        self.finalize(self.update(self.init(), seq))
+    }
+}
+
+//
+// The CRC used for the 16-bit FCS of RFC 1662, same as crc() above.
+//
+pub static CRC_16_X25: Crc = Crc::new(CrcParams {
+    width: 16,
+    poly: 0x1021,
+    init: 0xffff,
+    refin: true,
+    refout: true,
+    xorout: 0xffff,
+    check: 0x906e,
+});
+
+pub static CRC_16_MODBUS: Crc = Crc::new(CrcParams {
+    width: 16,
+    poly: 0x8005,
+    init: 0xffff,
+    refin: true,
+    refout: true,
+    xorout: 0x0000,
+    check: 0x4b37,
+});
+
+pub static CRC_16_CCITT_FALSE: Crc = Crc::new(CrcParams {
+    width: 16,
+    poly: 0x1021,
+    init: 0xffff,
+    refin: false,
+    refout: false,
+    xorout: 0x0000,
+    check: 0x29b1,
+});
+
+pub static CRC_8: Crc = Crc::new(CrcParams {
+    width: 8,
+    poly: 0x07,
+    init: 0x00,
+    refin: false,
+    refout: false,
+    xorout: 0x00,
+    check: 0xf4,
+});
+
+//
+// The CRC used for the 32-bit FCS of RFC 1662, same as crc32() above.
+//
+pub static CRC_32: Crc = Crc::new(CrcParams {
+    width: 32,
+    poly: 0x04c11db7,
+    init: 0xffffffff,
+    refin: true,
+    refout: true,
+    xorout: 0xffffffff,
+    check: 0xcbf43926,
+});
+
+pub static CRC_32C: Crc = Crc::new(CrcParams {
+    width: 32,
+    poly: 0x1edc6f41,
+    init: 0xffffffff,
+    refin: true,
+    refout: true,
+    xorout: 0xffffffff,
+    check: 0xe3069283,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc() {
+        assert_eq!(crc(INITIAL_FCS, b"123456789") ^ INITIAL_FCS, 0x906e);
+        assert_eq!(crc(INITIAL_FCS, &[0xff, 0x03]), ADDRESS_CONTROL_CHECKSUM);
+        assert_eq!(crc(INITIAL_FCS, b"123456789\x6e\x90"), GOOD_FCS);
+    }
+
+    #[test]
+    fn test_crc_slice8() {
+        // Every length and alignment of the tail must match the bytewise loop.
+        let data: Vec<u8> = (0..1024u32).map(|n| (n * 7 + n / 3) as u8).collect();
+        for start in 0..8 {
+            for end in start..data.len() {
+                let seq = &data[start..end];
+                assert_eq!(crc(INITIAL_FCS, seq), crc_bytewise(INITIAL_FCS, seq));
+                assert_eq!(crc(0x1234, seq), crc_bytewise(0x1234, seq));
+            }
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(
+            crc32(INITIAL_FCS32, b"123456789") ^ INITIAL_FCS32,
+            0xcbf43926
+        );
+        assert_eq!(
+            crc32(INITIAL_FCS32, &[0xff, 0x03]),
+            ADDRESS_CONTROL_CHECKSUM32
+        );
+        assert_eq!(
+            crc32(INITIAL_FCS32, b"123456789\x26\x39\xf4\xcb"),
+            GOOD_FCS32
+        );
+    }
+
+    #[test]
+    fn test_crc16_hasher() {
+        let mut fcs = Crc16::new();
+        fcs.update(b"1234");
+        fcs.update(b"56789");
+        assert_eq!(fcs.finalize(), 0x906e);
+        assert!(!fcs.verify());
+
+        // Appending the FCS leaves the good FCS residue.
+        fcs.update(&fcs.finalize().to_le_bytes());
+        assert!(fcs.verify());
+
+        let mut fcs = Crc16::with_address_control();
+        fcs.update(b"123456789");
+        let mut expected = Crc16::default();
+        expected.write(&[0xff, 0x03]);
+        expected.write(b"123456789");
+        assert_eq!(fcs.finalize() as u64, expected.finish());
+    }
+
+    #[test]
+    fn test_crc32_hasher() {
+        let mut fcs = Crc32::new();
+        fcs.update(b"123456789");
+        assert_eq!(fcs.finalize(), 0xcbf43926);
+        fcs.update(&fcs.finalize().to_le_bytes());
+        assert!(fcs.verify());
+    }
+
+    #[test]
+    fn test_crc_params_check() {
+        for crc in [
+            &CRC_16_X25,
+            &CRC_16_MODBUS,
+            &CRC_16_CCITT_FALSE,
+            &CRC_8,
+            &CRC_32,
+            &CRC_32C,
+        ] {
+            assert_eq!(crc.checksum(b"123456789"), crc.params().check);
+        }
+
+        // Algorithms that are not in a preset, CRC-5/USB and CRC-16/XMODEM,
+        // exercise widths below 8 and reflected output.
+        let crc_5_usb = Crc::new(CrcParams {
+            width: 5,
+            poly: 0x05,
+            init: 0x1f,
+            refin: true,
+            refout: true,
+            xorout: 0x1f,
+            check: 0x19,
+        });
+        assert_eq!(crc_5_usb.checksum(b"123456789"), 0x19);
+        let crc_16_xmodem = Crc::new(CrcParams {
+            width: 16,
+            poly: 0x1021,
+            init: 0x0000,
+            refin: false,
+            refout: false,
+            xorout: 0x0000,
+            check: 0x31c3,
+        });
+        assert_eq!(crc_16_xmodem.checksum(b"123456789"), 0x31c3);
+    }
+
+    #[test]
+    #[should_panic(expected = "CRC width must be 1 to 32 bits")]
+    fn test_crc_params_width() {
+        Crc::new(CrcParams {
+            width: 33,
+            ..CRC_32.params
+        });
+    }
+
+    #[test]
+    fn test_crc_params_match_fcs() {
+        let data: Vec<u8> = (0x00u8..=0xffu8).collect();
+
+        let sum = CRC_16_X25.update(CRC_16_X25.init(), &data);
+        assert_eq!(sum as u16, crc(INITIAL_FCS, &data));
+
+        let sum = CRC_32.update(CRC_32.init(), &data);
+        assert_eq!(sum, crc32(INITIAL_FCS32, &data));
+
+        // Data may be fed in pieces.
+        let sum = CRC_16_MODBUS.update(CRC_16_MODBUS.init(), &data[..100]);
+        let sum = CRC_16_MODBUS.update(sum, &data[100..]);
+        assert_eq!(CRC_16_MODBUS.finalize(sum), CRC_16_MODBUS.checksum(&data));
+    }
+}
@@ -0,0 +1,217 @@
+// Storage for the frame the Framer is collecting. A Vec grows as needed, an
+// ArrayBuffer has a fixed capacity so that framing needs no heap, e.g. on the
+// microcontroller side of the link. Frames collected in a BytesMut are split
+// off one shared allocation and can be frozen into Bytes.
+
+use alloc::vec::Vec;
+use bytes::{Buf, BufMut, BytesMut};
+use core::{fmt, mem};
+
+pub trait FrameBuffer: Default + AsRef<[u8]> {
+    // The most bytes the buffer can hold.
+    const CAPACITY: usize;
+
+    // Appends a byte, callers keep within CAPACITY.
+    fn push(&mut self, byte: u8);
+
+    // Inserts a byte at the front, callers keep within CAPACITY.
+    fn push_front(&mut self, byte: u8);
+
+    // Overwrites the first byte, callers keep the buffer non-empty.
+    fn set_front(&mut self, byte: u8);
+
+    fn truncate(&mut self, len: usize);
+
+    // Removes the first n bytes.
+    fn remove_front(&mut self, n: usize);
+
+    // Makes room for at least additional more bytes, if the buffer can grow.
+    fn reserve(&mut self, _additional: usize) {}
+
+    // Takes the collected frame, leaving the buffer empty for the next one.
+    fn take_frame(&mut self) -> Self {
+        mem::take(self)
+    }
+
+    fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    fn len(&self) -> usize {
+        self.as_ref().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl FrameBuffer for Vec<u8> {
+    const CAPACITY: usize = usize::MAX;
+
+    fn push(&mut self, byte: u8) {
+        Vec::push(self, byte);
+    }
+
+    fn push_front(&mut self, byte: u8) {
+        self.insert(0, byte);
+    }
+
+    fn set_front(&mut self, byte: u8) {
+        self[0] = byte;
+    }
+
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len);
+    }
+
+    fn remove_front(&mut self, n: usize) {
+        self.drain(0..n);
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        Vec::reserve(self, additional);
+    }
+}
+
+impl FrameBuffer for BytesMut {
+    const CAPACITY: usize = usize::MAX;
+
+    fn push(&mut self, byte: u8) {
+        self.put_u8(byte);
+    }
+
+    // Copies the frame, only for Protocol Field Compression with Address and
+    // Control Field Compression, so no Address and Control fields make room.
+    fn push_front(&mut self, byte: u8) {
+        let mut frame = BytesMut::with_capacity(self.len() + 1);
+        frame.put_u8(byte);
+        frame.extend_from_slice(self);
+        *self = frame;
+    }
+
+    fn set_front(&mut self, byte: u8) {
+        self[0] = byte;
+    }
+
+    fn truncate(&mut self, len: usize) {
+        BytesMut::truncate(self, len);
+    }
+
+    fn remove_front(&mut self, n: usize) {
+        self.advance(n);
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        BytesMut::reserve(self, additional);
+    }
+
+    // The rest of the allocation is kept for the next frame.
+    fn take_frame(&mut self) -> Self {
+        self.split()
+    }
+}
+
+// A buffer of at most N bytes, held inline.
+#[derive(Clone)]
+pub struct ArrayBuffer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> ArrayBuffer<N> {
+    pub fn new() -> Self {
+        ArrayBuffer {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+}
+
+impl<const N: usize> Default for ArrayBuffer<N> {
+    fn default() -> Self {
+        ArrayBuffer::new()
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for ArrayBuffer<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl<const N: usize> FrameBuffer for ArrayBuffer<N> {
+    const CAPACITY: usize = N;
+
+    fn push(&mut self, byte: u8) {
+        self.bytes[self.len] = byte;
+        self.len += 1;
+    }
+
+    fn push_front(&mut self, byte: u8) {
+        self.bytes.copy_within(0..self.len, 1);
+        self.bytes[0] = byte;
+        self.len += 1;
+    }
+
+    fn set_front(&mut self, byte: u8) {
+        self.bytes[0] = byte;
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    fn remove_front(&mut self, n: usize) {
+        self.bytes.copy_within(n..self.len, 0);
+        self.len -= n;
+    }
+}
+
+// Only the bytes held take part, not the unused capacity.
+impl<const N: usize> PartialEq for ArrayBuffer<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_ref() == other.as_ref()
+    }
+}
+
+impl<const N: usize> Eq for ArrayBuffer<N> {}
+
+impl<const N: usize> fmt::Debug for ArrayBuffer<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_ref().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_array_buffer() {
+        let mut buffer = ArrayBuffer::<4>::new();
+        assert!(buffer.is_empty());
+
+        buffer.push(0x02);
+        buffer.push(0x03);
+        buffer.push(0x04);
+        buffer.push_front(0x01);
+        assert_eq!(buffer.as_ref(), [0x01, 0x02, 0x03, 0x04]);
+
+        buffer.remove_front(1);
+        buffer.truncate(2);
+        assert_eq!(buffer.as_ref(), [0x02, 0x03]);
+        buffer.set_front(0x00);
+        assert_eq!(buffer.as_ref(), [0x00, 0x03]);
+        buffer.set_front(0x02);
+        assert_eq!(buffer.len(), 2);
+
+        // Unused capacity does not take part in comparisons.
+        let mut other = ArrayBuffer::<4>::new();
+        other.push(0xff);
+        other.clear();
+        other.push(0x02);
+        other.push(0x03);
+        assert_eq!(buffer, other);
+    }
+}
@@ -17,6 +17,8 @@
 
 #include "hdlc.h"
 
+#include <string.h>
+
 /*
  * Encode and decode PPP packets from and into HDLC frames.
  *
@@ -27,6 +29,10 @@
 #define in_sending_accm(byte) \
     ((byte) < 0x20 || ((byte)&0x7f) == 0x7d || ((byte)&0x7f) == 0x7e)
 
+/*
+ * The receiving ACCM is fixed to all of 0x00-0x1f, unlike the Rust Framer's
+ * Accm it cannot be negotiated per link.
+ */
 #define in_receiving_accm(byte) \
     ((byte) < 0x20)
 
@@ -90,25 +96,18 @@ static uint16_t frame_checksum_16bit(uint16_t sum, const uint8_t *seq, size_t le
  */
 static const uint16_t address_control_checksum = 0x3de3;
 
-/*
- * Each frame begins with a Flag Sequence.
- * Only one Flag Sequence is required between two frames.
- * The first frame begins with a Flag Sequence.
- * Subsequent frames rely on the Flag Sequence that ends the previous frame.
- */
-static int need_flag_sequence;
-
 /*
  * Upon connection, the first frame begins with a Flag Sequence.
  */
-void init_hdlc(void)
+void init_hdlc(struct hdlc_encoder *encoder)
 {
-    need_flag_sequence = 1;
+    encoder->need_flag_sequence = 1;
 }
 
 /*
  * Wraps a PPP packet into an HDLC frame and write it to a buffer.
  *
+ * @param[in,out] encoder  The encoder state of the link.
  * @param[out] frame    The buffer to store the encoded frame.
  * @param[in]  frmsize  The output buffer size.
  * @param[in]  packet   The buffer containing the packet.
@@ -117,7 +116,7 @@ void init_hdlc(void)
  *                      HDLC-encoded frame length) or ERR_HDLC_BUFFER_TOO_SMALL
  *                      if the output buffer is too small
  */
-ssize_t hdlc_encode(uint8_t *frame, size_t frmsize,
+ssize_t hdlc_encode(struct hdlc_encoder *encoder, uint8_t *frame, size_t frmsize,
                     const uint8_t *packet, size_t pktsize)
 {
     ssize_t written = 0;
@@ -131,7 +130,7 @@ ssize_t hdlc_encode(uint8_t *frame, size_t frmsize,
 
     // In theory each frame begins with a Flag Sequence, but it is omitted
     // if the previous frame ends with a Flag Sequence.
-    if (need_flag_sequence)
+    if (encoder->need_flag_sequence)
         frame[written++] = 0x7e;
 
     // Escape and write Frame Address and Control fields
@@ -188,7 +187,7 @@ ssize_t hdlc_encode(uint8_t *frame, size_t frmsize,
 
     // Each frame ends with a Flag Sequence
     frame[written++] = 0x7e;
-    need_flag_sequence = 0;
+    encoder->need_flag_sequence = 0;
 
     return written;
 }
@@ -255,7 +254,7 @@ ssize_t hdlc_decode(const uint8_t *frame, size_t frmsize,
 {
     off_t start = 0;
     ssize_t written = 0;
-    int has_address_control_fields = 0;
+    int has_address_control_fields;
     int i;
     int in_escape;
     uint16_t checksum;
@@ -263,15 +262,8 @@ ssize_t hdlc_decode(const uint8_t *frame, size_t frmsize,
     if (frmsize < 5)
         return ERR_HDLC_INVALID_FRAME;
 
-    // Remove Address and escaped Control fields
-    if (frame[0] == 0xff && frame[1] == 0x7d && frame[2] == (0x03 ^ 0x20))
-    {
-        start += 3;
-        has_address_control_fields = 1;
-    }
-
     in_escape = 0;
-    for (i = start; i < frmsize; i++)
+    for (i = 0; i < frmsize; i++)
     {
         uint8_t byte = frame[i];
 
@@ -284,12 +276,17 @@ ssize_t hdlc_decode(const uint8_t *frame, size_t frmsize,
         }
         else if (in_escape)
         {
+            // Characters introduced by the DCE may also follow a Control Escape
+            if (in_receiving_accm(byte))
+                continue;
             byte ^= 0x20;
             in_escape = 0;
         }
         else if (in_receiving_accm(byte))
         {
-            continue; // Drop characters possibly introduced by DCE
+            // Keep an unescaped Control field, from peers not escaping it
+            if (!(byte == 0x03 && written == 1 && packet[0] == 0xff))
+                continue; // Drop characters possibly introduced by DCE
         }
         if (written >= pktsize)
             return ERR_HDLC_BUFFER_TOO_SMALL;
@@ -298,18 +295,28 @@ ssize_t hdlc_decode(const uint8_t *frame, size_t frmsize,
     if (in_escape)
         return ERR_HDLC_INVALID_FRAME;
 
-    if (written < 3)
+    // Address and Control fields are covered by the Frame Check Sequence like
+    // the rest of the frame
+    has_address_control_fields = written >= 2 && packet[0] == 0xff && packet[1] == 0x03;
+    if (has_address_control_fields)
+        start = 2;
+
+    // The packet may be empty, but not the Frame Check Sequence
+    if (written - start < 2)
         return ERR_HDLC_INVALID_FRAME;
 
     // Control Frame Check Sequence field validity and remove it
-    if (has_address_control_fields)
-        checksum = address_control_checksum;
-    else
-        checksum = 0xffff;
-    checksum = frame_checksum_16bit(checksum, packet, written);
+    checksum = frame_checksum_16bit(0xffff, packet, written);
     if (checksum != 0xf0b8)
         return ERR_HDLC_BAD_CHECKSUM;
     written -= 2;
 
+    // Remove Address and Control fields
+    if (has_address_control_fields)
+    {
+        memmove(packet, packet + start, written - start);
+        written -= start;
+    }
+
     return written;
 }
@@ -39,9 +39,22 @@
 #define estimated_encoded_size(ppp_pkt_len) (9 + 2 * (ppp_pkt_len))
 #define estimated_decoded_size(hdlc_frm_len) (hdlc_frm_len)
 
-void init_hdlc(void);
+/*
+ * Each frame begins with a Flag Sequence.
+ * Only one Flag Sequence is required between two frames.
+ * The first frame begins with a Flag Sequence.
+ * Subsequent frames rely on the Flag Sequence that ends the previous frame.
+ *
+ * So the encoder keeps track, per link, of whether a Flag Sequence is needed.
+ */
+struct hdlc_encoder
+{
+    int need_flag_sequence;
+};
+
+void init_hdlc(struct hdlc_encoder *encoder);
 
-ssize_t hdlc_encode(uint8_t *frame, size_t frmsize,
+ssize_t hdlc_encode(struct hdlc_encoder *encoder, uint8_t *frame, size_t frmsize,
                     const uint8_t *packet, size_t pktsize);
 
 ssize_t hdlc_find_frame(const uint8_t *buffer, size_t bufsize, off_t *start);
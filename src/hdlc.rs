@@ -5,35 +5,273 @@
 pub const FLAG: u8 = 0x7e;
 pub const CONTROL_ESCAPE: u8 = 0x7d;
 
+// The "all stations" Address and the Unnumbered Information Control fields.
+pub const ADDRESS: u8 = 0xff;
+pub const CONTROL: u8 = 0x03;
+
+// The Protocol field of LCP packets, never sent with compressed fields.
+pub const LCP_PROTOCOL: [u8; 2] = [0xc0, 0x21];
+
+// Frames longer than this, once unescaped, are discarded by the Framer.
+pub const DEFAULT_MAX_FRAME_LEN: usize = 4096;
+
 enum FramerState {
     Frame,
     Escaped,
     Flag,
 }
 
-pub struct Framer {
-    frame: Vec<u8>,
+// What the Framer found in the byte stream.
+#[derive(Debug, PartialEq)]
+pub enum FramerEvent<B = Vec<u8>> {
+    // A good frame, with the Address, Control and FCS fields removed.
+    Frame(B),
+    // A frame too short to contain a Frame Check Sequence.
+    InvalidFrame,
+    // A frame whose Frame Check Sequence does not match its contents.
+    BadChecksum,
+    // A frame longer than the maximum frame length, it has been discarded.
+    Overflow,
+    // A frame aborted by a Control Escape followed by a Flag Sequence.
+    Abort,
+    // A frame containing a Control Escape followed by another Control Escape.
+    InvalidEscape,
+}
+
+impl<B> FramerEvent<B> {
+    // Converts the frame, e.g. to freeze a BytesMut into Bytes.
+    pub fn map<C>(self, f: impl FnOnce(B) -> C) -> FramerEvent<C> {
+        match self {
+            FramerEvent::Frame(frame) => FramerEvent::Frame(f(frame)),
+            FramerEvent::InvalidFrame => FramerEvent::InvalidFrame,
+            FramerEvent::BadChecksum => FramerEvent::BadChecksum,
+            FramerEvent::Overflow => FramerEvent::Overflow,
+            FramerEvent::Abort => FramerEvent::Abort,
+            FramerEvent::InvalidEscape => FramerEvent::InvalidEscape,
+        }
+    }
+}
+
+// Frames are collected in a Vec by default, or in any other FrameBuffer such
+// as a fixed capacity ArrayBuffer.
+pub struct Framer<B = Vec<u8>> {
+    frame: B,
+    // Control characters were dropped from the frame being collected, so it
+    // is not empty even if nothing was collected.
+    dropped: bool,
     state: FramerState,
+    max_frame_len: usize,
+    overflows: u64,
+    accm: Accm,
+    pfc: bool,
+    fcs_width: FcsWidth,
+}
+use alloc::vec::Vec;
+
+use crate::crc::{Crc16, Crc32};
+use crate::frame_buffer::FrameBuffer;
+
+// The size of the Frame Check Sequence, RFC 1662 section C.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FcsWidth {
+    #[default]
+    Fcs16,
+    Fcs32,
 }
-use std::mem;
 
-pub fn in_sending_accm(byte: u8) -> bool {
-    byte < 0x20 || (byte & 0x7f) == 0x7d || (byte & 0x7f) == 0x7e
+impl FcsWidth {
+    // The number of bytes in the FCS field.
+    pub fn fcs_len(&self) -> usize {
+        match self {
+            FcsWidth::Fcs16 => 2,
+            FcsWidth::Fcs32 => 4,
+        }
+    }
+
+    // Whether an unescaped frame, including its FCS field, is intact.
+    fn is_good(&self, frame: &[u8]) -> bool {
+        match self {
+            FcsWidth::Fcs16 => {
+                let mut fcs = Crc16::new();
+                fcs.update(frame);
+                fcs.verify()
+            }
+            FcsWidth::Fcs32 => {
+                let mut fcs = Crc32::new();
+                fcs.update(frame);
+                fcs.verify()
+            }
+        }
+    }
+
+    // The FCS field of a packet, least significant byte first, optionally
+    // preceded by the Address and Control fields. Only the first fcs_len
+    // bytes are used, so encoding does not allocate for it.
+    fn fcs(&self, address_control: bool, packet: &[u8]) -> ([u8; 4], usize) {
+        match self {
+            FcsWidth::Fcs16 => {
+                let mut fcs = if address_control {
+                    Crc16::with_address_control()
+                } else {
+                    Crc16::new()
+                };
+                fcs.update(packet);
+                let [low, high] = fcs.finalize().to_le_bytes();
+                ([low, high, 0, 0], 2)
+            }
+            FcsWidth::Fcs32 => {
+                let mut fcs = if address_control {
+                    Crc32::with_address_control()
+                } else {
+                    Crc32::new()
+                };
+                fcs.update(packet);
+                (fcs.finalize().to_le_bytes(), 4)
+            }
+        }
+    }
 }
 
-pub fn in_receiving_accm(byte: u8) -> bool {
-    byte < 0x20
+// The Async-Control-Character-Map, as negotiated by LCP (RFC 1662 section 7.1).
+//
+// Bit n of the map is set when control character n must be escaped when
+// sending. The same map tells the receiver which unescaped control characters
+// to drop, as they may have been introduced by the DCE. The extended set
+// escapes any other byte values when sending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Accm {
+    map: u32,
+    extended: [u32; 8],
 }
 
-impl Framer {
-    pub fn new() -> Self {
+impl Accm {
+    pub fn new(map: u32) -> Self {
+        Accm {
+            map,
+            extended: [0; 8],
+        }
+    }
+
+    // No control characters are escaped or dropped.
+    pub fn none() -> Self {
+        Accm::new(0)
+    }
+
+    // Adds a byte value to the extended escape set.
+    pub fn with_escaped(mut self, byte: u8) -> Self {
+        self.extended[(byte >> 5) as usize] |= 1 << (byte & 0x1f);
+        self
+    }
+
+    pub fn map(&self) -> u32 {
+        self.map
+    }
+
+    // Whether a byte must be escaped when sending. The Flag Sequence and
+    // Control Escape always are.
+    pub fn is_escaped(&self, byte: u8) -> bool {
+        byte == FLAG
+            || byte == CONTROL_ESCAPE
+            || self.is_dropped(byte)
+            || self.extended[(byte >> 5) as usize] & (1 << (byte & 0x1f)) != 0
+    }
+
+    // Whether an unescaped byte must be dropped when receiving.
+    pub fn is_dropped(&self, byte: u8) -> bool {
+        byte < 0x20 && self.map & (1 << byte) != 0
+    }
+}
+
+impl Default for Accm {
+    // All control characters are escaped until LCP negotiates otherwise. Like
+    // hdlc.c we also escape 0xfd and 0xfe.
+    fn default() -> Self {
+        Accm::new(0xffffffff)
+            .with_escaped(CONTROL_ESCAPE | 0x80)
+            .with_escaped(FLAG | 0x80)
+    }
+}
+
+impl<B: FrameBuffer> Default for Framer<B> {
+    fn default() -> Self {
         Framer {
-            frame: Vec::<u8>::new(),
+            frame: B::default(),
+            dropped: false,
             state: FramerState::Flag,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN.min(B::CAPACITY),
+            overflows: 0,
+            // Our devices do not escape control characters, so keep them all.
+            accm: Accm::none(),
+            pfc: false,
+            fcs_width: FcsWidth::Fcs16,
         }
     }
+}
+
+impl Framer {
+    pub fn new() -> Self {
+        Framer::default()
+    }
+}
+
+impl<B: FrameBuffer> Framer<B> {
+    pub fn with_fcs_width(mut self, fcs_width: FcsWidth) -> Self {
+        self.fcs_width = fcs_width;
+        self
+    }
+
+    // Protocol Field Compression, expand single byte Protocol fields.
+    pub fn with_pfc(mut self, pfc: bool) -> Self {
+        self.pfc = pfc;
+        self
+    }
+
+    // The receiving ACCM, unescaped control characters in it are dropped.
+    pub fn with_accm(mut self, accm: Accm) -> Self {
+        self.accm = accm;
+        self
+    }
+
+    // Limited to the capacity of the frame buffer.
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len.min(B::CAPACITY);
+        self
+    }
+
+    pub fn max_frame_len(&self) -> usize {
+        self.max_frame_len
+    }
 
-    pub fn find_frame(&mut self, byte: u8) -> Option<Vec<u8>> {
+    // The number of frames discarded for being too long.
+    pub fn overflows(&self) -> u64 {
+        self.overflows
+    }
+
+    // Discards the frame being collected as it is too long, then hunts for
+    // the next Flag Sequence to resynchronize.
+    pub(crate) fn discard(&mut self) -> FramerEvent<B> {
+        self.frame.clear();
+        self.dropped = false;
+        self.state = FramerState::Flag;
+        self.overflows += 1;
+        FramerEvent::Overflow
+    }
+
+    // Whether an unescaped byte is dropped, given the frame so far. An
+    // unescaped Control field is kept, from peers not escaping it.
+    fn is_dropped(&self, frame: &[u8], byte: u8) -> bool {
+        self.accm.is_dropped(byte) && !(byte == CONTROL && frame == [ADDRESS])
+    }
+
+    fn collect(&mut self, byte: u8) -> Option<FramerEvent<B>> {
+        if self.frame.len() >= self.max_frame_len {
+            return Some(self.discard());
+        }
+        self.frame.push(byte);
+        None
+    }
+
+    pub fn find_frame(&mut self, byte: u8) -> Option<FramerEvent<B>> {
         match self.state {
             FramerState::Flag if byte == FLAG => {
                 self.state = FramerState::Frame;
@@ -41,11 +279,11 @@ impl Framer {
             }
             FramerState::Flag => None,
             FramerState::Frame if byte == FLAG => {
-                // Frame is complete, ship it out.
-                if !self.frame.is_empty() {
-                    let mut new_frame = Vec::<u8>::new();
-                    mem::swap(&mut self.frame, &mut new_frame);
-                    return Some(new_frame);
+                // Frame is complete, check it and ship it out.
+                if !self.frame.is_empty() || self.dropped {
+                    self.dropped = false;
+                    let frame = self.frame.take_frame();
+                    return Some(self.check_frame(frame));
                 }
                 None
             }
@@ -54,18 +292,275 @@ impl Framer {
                 self.state = FramerState::Escaped;
                 None
             }
+            FramerState::Frame if self.is_dropped(self.frame.as_ref(), byte) => {
+                // Drop characters possibly introduced by the DCE.
+                self.dropped = true;
+                None
+            }
             FramerState::Frame => {
                 // Collect frame bytes.
-                self.frame.push(byte);
+                self.collect(byte)
+            }
+            FramerState::Escaped if byte == FLAG => {
+                // Abort sequence, drop the frame. The Flag Sequence starts the next one.
+                self.frame.clear();
+                self.dropped = false;
+                self.state = FramerState::Frame;
+                Some(FramerEvent::Abort)
+            }
+            FramerState::Escaped if byte == CONTROL_ESCAPE => {
+                // Invalid escape, drop the frame and hunt for the next Flag Sequence.
+                self.frame.clear();
+                self.dropped = false;
+                self.state = FramerState::Flag;
+                Some(FramerEvent::InvalidEscape)
+            }
+            FramerState::Escaped if self.accm.is_dropped(byte) => {
+                // Characters introduced by the DCE may also arrive between a
+                // Control Escape and the byte it escapes, drop them too.
+                self.dropped = true;
                 None
             }
             FramerState::Escaped => {
                 // Collect escaped frame byte.
-                self.frame.push(byte ^ 0x20);
                 self.state = FramerState::Frame;
-                None
+                self.collect(byte ^ 0x20)
+            }
+        }
+    }
+
+    // Unescapes and checks a whole frame found between two Flag Sequences.
+    pub fn decode_frame(&mut self, raw: &[u8]) -> FramerEvent<B> {
+        match self.unescape_frame(raw) {
+            Ok(frame) => self.check_frame(frame),
+            Err(event) => event,
+        }
+    }
+
+    // Unescapes a whole frame without checking it, or returns the event
+    // ending it early.
+    pub(crate) fn unescape_frame(&mut self, raw: &[u8]) -> Result<B, FramerEvent<B>> {
+        let mut frame = B::default();
+        frame.reserve(raw.len().min(self.max_frame_len));
+        let mut escaped = false;
+        for byte in raw {
+            if let Some(byte) = self.unescape(&mut escaped, frame.as_ref(), *byte)? {
+                // Like collect, only bytes kept count towards the limit.
+                if frame.len() >= self.max_frame_len {
+                    self.overflows += 1;
+                    return Err(FramerEvent::Overflow);
+                }
+                frame.push(byte);
+            }
+        }
+        // A Control Escape just before the closing Flag Sequence aborts the frame.
+        if escaped {
+            return Err(FramerEvent::Abort);
+        }
+        Ok(frame)
+    }
+
+    // Like decode_frame, but unescapes the frame where it is in the raw
+    // buffer, so a good frame is returned in that same buffer without copying.
+    pub fn decode_frame_in_place(&mut self, mut raw: B) -> FramerEvent<B>
+    where
+        B: AsMut<[u8]>,
+    {
+        let bytes = raw.as_mut();
+        let mut len = 0;
+        let mut escaped = false;
+        for i in 0..bytes.len() {
+            // Unescaping never writes ahead of the byte being read.
+            match self.unescape(&mut escaped, &bytes[..len], bytes[i]) {
+                Ok(Some(_)) if len >= self.max_frame_len => {
+                    self.overflows += 1;
+                    return FramerEvent::Overflow;
+                }
+                Ok(Some(byte)) => {
+                    bytes[len] = byte;
+                    len += 1;
+                }
+                Ok(None) => {}
+                Err(event) => return event,
+            }
+        }
+        if escaped {
+            return FramerEvent::Abort;
+        }
+        raw.truncate(len);
+        self.check_frame(raw)
+    }
+
+    // Unescapes the next byte of a whole frame, given the frame unescaped so
+    // far. Returns the unescaped byte if there is one, or the event ending the
+    // frame early.
+    fn unescape(
+        &self,
+        escaped: &mut bool,
+        frame: &[u8],
+        byte: u8,
+    ) -> Result<Option<u8>, FramerEvent<B>> {
+        if *escaped {
+            if byte == CONTROL_ESCAPE {
+                return Err(FramerEvent::InvalidEscape);
+            }
+            if self.accm.is_dropped(byte) {
+                return Ok(None);
             }
+            *escaped = false;
+            Ok(Some(byte ^ 0x20))
+        } else if byte == CONTROL_ESCAPE {
+            *escaped = true;
+            Ok(None)
+        } else if self.is_dropped(frame, byte) {
+            Ok(None)
+        } else {
+            Ok(Some(byte))
+        }
+    }
+
+    // Verifies the Frame Check Sequence of an unescaped frame then strips it,
+    // along with the Address and Control fields if present.
+    pub(crate) fn check_frame(&self, mut frame: B) -> FramerEvent<B> {
+        // Like hdlc.c, a frame must hold its FCS field after the Address and
+        // Control fields, though the packet may be empty.
+        let fcs_len = self.fcs_width.fcs_len();
+        let address_control_len = if frame.as_ref().starts_with(&[ADDRESS, CONTROL]) {
+            2
+        } else {
+            0
+        };
+        if frame.len() < address_control_len + fcs_len {
+            return FramerEvent::InvalidFrame;
+        }
+        if !self.fcs_width.is_good(frame.as_ref()) {
+            return FramerEvent::BadChecksum;
+        }
+        frame.truncate(frame.len() - fcs_len);
+
+        // A compressed Protocol field is a single odd byte, restore the
+        // leading zero byte so the application always sees two bytes.
+        let compressed = self.pfc
+            && frame
+                .as_ref()
+                .get(address_control_len)
+                .is_some_and(|byte| byte & 0x01 == 0x01);
+
+        // Peers using Address and Control Field Compression omit them. When
+        // present, the Control field makes room for the zero byte in place.
+        if compressed && address_control_len == 2 {
+            frame.remove_front(1);
+            frame.set_front(0x00);
+        } else {
+            frame.remove_front(address_control_len);
+            if compressed {
+                frame.push_front(0x00);
+            }
+        }
+        FramerEvent::Frame(frame)
+    }
+}
+
+pub struct Encoder {
+    // Each frame begins with a Flag Sequence, but only one Flag Sequence is
+    // required between two frames. Subsequent frames rely on the Flag
+    // Sequence that ends the previous frame.
+    need_flag_sequence: bool,
+    accm: Accm,
+    acfc: bool,
+    pfc: bool,
+    fcs_width: FcsWidth,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Encoder::new()
+    }
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Encoder {
+            need_flag_sequence: true,
+            accm: Accm::default(),
+            acfc: false,
+            pfc: false,
+            fcs_width: FcsWidth::Fcs16,
+        }
+    }
+
+    pub fn with_fcs_width(mut self, fcs_width: FcsWidth) -> Self {
+        self.fcs_width = fcs_width;
+        self
+    }
+
+    // Address and Control Field Compression, omit the Address and Control fields.
+    pub fn with_acfc(mut self, acfc: bool) -> Self {
+        self.acfc = acfc;
+        self
+    }
+
+    // Protocol Field Compression, send Protocol fields below 0x100 as one byte.
+    // The packet is expected to start with its two byte Protocol field.
+    pub fn with_pfc(mut self, pfc: bool) -> Self {
+        self.pfc = pfc;
+        self
+    }
+
+    // The sending ACCM, bytes in it are escaped.
+    pub fn with_accm(mut self, accm: Accm) -> Self {
+        self.accm = accm;
+        self
+    }
+
+    // Upon (re)connection, the first frame begins with a Flag Sequence.
+    pub fn reset(&mut self) {
+        self.need_flag_sequence = true;
+    }
+
+    // Wraps a packet into an HDLC frame, escaping as required by RFC 1662.
+    pub fn encode(&mut self, packet: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::<u8>::with_capacity(9 + 2 * packet.len());
+
+        if self.need_flag_sequence {
+            frame.push(FLAG);
+        }
+
+        // LCP packets always carry the Address and Control fields, so that
+        // peers recognise them whatever was negotiated (RFC 1661 section 6.6).
+        let acfc = self.acfc && !packet.starts_with(&LCP_PROTOCOL);
+        if !acfc {
+            push_escaped(&mut frame, &self.accm, ADDRESS);
+            push_escaped(&mut frame, &self.accm, CONTROL);
+        }
+
+        let packet = match packet {
+            [0x00, protocol, ..] if self.pfc && protocol & 0x01 == 0x01 => &packet[1..],
+            _ => packet,
+        };
+        for byte in packet {
+            push_escaped(&mut frame, &self.accm, *byte);
         }
+
+        let (fcs, fcs_len) = self.fcs_width.fcs(!acfc, packet);
+        for byte in &fcs[..fcs_len] {
+            push_escaped(&mut frame, &self.accm, *byte);
+        }
+
+        // Each frame ends with a Flag Sequence
+        frame.push(FLAG);
+        self.need_flag_sequence = false;
+
+        frame
+    }
+}
+
+fn push_escaped(frame: &mut Vec<u8>, accm: &Accm, byte: u8) {
+    if accm.is_escaped(byte) {
+        frame.push(CONTROL_ESCAPE);
+        frame.push(byte ^ 0x20);
+    } else {
+        frame.push(byte);
     }
 }
 
@@ -73,22 +568,27 @@ impl Framer {
 mod tests {
     //use crate::serial_port_test::epoch_seconds;
     use super::*;
+    use crate::frame_buffer::ArrayBuffer;
+    use bytes::BytesMut;
 
     #[test]
     fn test_find_frame() {
-        let message_1: Vec<u8> = vec![0x01, 0x02, 0x03, 0x05];
-        let message_2: Vec<u8> = vec![0x06, 0x07, 0x08, 0x09];
-        let message_3_in: Vec<u8> = vec![0x0a, 0x0b, 0x7d, 0x5e, 0x0d];
+        // Messages followed by their FCS.
+        let message_1_in: Vec<u8> = vec![0x01, 0x02, 0x03, 0x05, 0x18, 0x28];
+        let message_1_out: Vec<u8> = vec![0x01, 0x02, 0x03, 0x05];
+        let message_2_in: Vec<u8> = vec![0x06, 0x07, 0x08, 0x09, 0x40, 0x68];
+        let message_2_out: Vec<u8> = vec![0x06, 0x07, 0x08, 0x09];
+        let message_3_in: Vec<u8> = vec![0x0a, 0x0b, 0x7d, 0x5e, 0x0d, 0xe7, 0xb8];
         let message_3_out: Vec<u8> = vec![0x0a, 0x0b, 0x7e, 0x0d];
-        let message_4_in: Vec<u8> = vec![0x10, 0x7d, 0x5d, 0x12, 0x13];
+        let message_4_in: Vec<u8> = vec![0x10, 0x7d, 0x5d, 0x12, 0x13, 0xe3, 0xc4];
         let message_4_out: Vec<u8> = vec![0x10, 0x7d, 0x12, 0x13];
 
-        let mut messages = vec![0x55u8, 0x55u8, 0x55u8, 0x55u8, 0x55u8, 0x55u8].to_vec();
+        let mut messages = [0x55u8; 6].to_vec();
         messages.push(0x7e);
-        messages.append(&mut message_1.clone());
+        messages.append(&mut message_1_in.clone());
         messages.push(0x7e);
         messages.push(0x7e);
-        messages.append(&mut message_2.clone());
+        messages.append(&mut message_2_in.clone());
         messages.push(0x7e);
         messages.append(&mut message_3_in.clone());
         messages.push(0x7e);
@@ -99,16 +599,413 @@ mod tests {
         println!("{:x?}", messages);
 
         let mut framer = Framer::new();
-        let mut frames: Vec<Vec<u8>> = vec![];
+        let mut frames: Vec<FramerEvent> = vec![];
         for byte in messages {
             if let Some(x) = framer.find_frame(byte) {
                 frames.push(x);
                 println!("{:x?}", frames);
             }
         }
-        assert_eq!(frames[0], message_1);
-        assert_eq!(frames[1], message_2);
-        assert_eq!(frames[2], message_3_out);
-        assert_eq!(frames[3], message_4_out);
+        assert_eq!(frames[0], FramerEvent::Frame(message_1_out));
+        assert_eq!(frames[1], FramerEvent::Frame(message_2_out));
+        assert_eq!(frames[2], FramerEvent::Frame(message_3_out));
+        assert_eq!(frames[3], FramerEvent::Frame(message_4_out));
+    }
+
+    #[test]
+    fn test_find_frame_bad_checksum() {
+        let messages: Vec<u8> = vec![
+            0x7e, 0x01, 0x02, 0x03, 0x05, 0x18, 0x29, 0x7e, 0x01, 0x7e, 0x01, 0x02, 0x03, 0x05,
+            0x18, 0x28, 0x7e,
+        ];
+
+        let mut framer = Framer::new();
+        let frames: Vec<FramerEvent> = messages
+            .into_iter()
+            .filter_map(|byte| framer.find_frame(byte))
+            .collect();
+        assert_eq!(frames[0], FramerEvent::BadChecksum);
+        assert_eq!(frames[1], FramerEvent::InvalidFrame);
+        assert_eq!(frames[2], FramerEvent::Frame(vec![0x01, 0x02, 0x03, 0x05]));
+    }
+
+    #[test]
+    fn test_encode() {
+        let packet: Vec<u8> = vec![0x40, 0x41, 0x42, 0x7e, 0x44, 0x03];
+
+        let mut encoder = Encoder::new();
+        let encoded = encoder.encode(&packet);
+        assert_eq!(
+            encoded,
+            vec![
+                0x7e, 0xff, 0x7d, 0x23, 0x40, 0x41, 0x42, 0x7d, 0x5e, 0x44, 0x7d, 0x23, 0x7d, 0x3c,
+                0xbd, 0x7e
+            ]
+        );
+
+        // Only the first frame begins with a Flag Sequence.
+        let encoded = encoder.encode(&packet);
+        assert_eq!(encoded[0], 0xff);
+        assert_eq!(encoded[encoded.len() - 1], FLAG);
+
+        // Until the encoder is reset.
+        encoder.reset();
+        let encoded = encoder.encode(&packet);
+        assert_eq!(encoded[0], FLAG);
+    }
+
+    #[test]
+    fn test_encode_find_frame() {
+        // Build a "message" containing all possible byte values.
+        let packet: Vec<u8> = (0x00u8..=0xffu8).collect();
+
+        let mut encoder = Encoder::new();
+        let mut encoded = encoder.encode(&packet);
+        encoded.append(&mut encoder.encode(&packet));
+
+        let mut framer = Framer::new();
+        let mut frames: Vec<FramerEvent> = vec![];
+        for byte in encoded {
+            if let Some(frame) = framer.find_frame(byte) {
+                frames.push(frame);
+            }
+        }
+        assert_eq!(frames.len(), 2);
+        for frame in frames {
+            assert_eq!(frame, FramerEvent::Frame(packet.clone()));
+        }
+    }
+
+    #[test]
+    fn test_encode_find_frame_empty() {
+        // Frames without a packet make the round trip, with or without the
+        // Address and Control fields.
+        for acfc in [false, true] {
+            let mut encoder = Encoder::new().with_acfc(acfc);
+            let encoded = encoder.encode(&[]);
+
+            let mut framer = Framer::new();
+            let frames: Vec<FramerEvent> = encoded
+                .iter()
+                .filter_map(|byte| framer.find_frame(*byte))
+                .collect();
+            assert_eq!(frames, vec![FramerEvent::Frame(vec![])]);
+
+            let raw = &encoded[1..encoded.len() - 1];
+            assert_eq!(framer.decode_frame(raw), FramerEvent::Frame(vec![]));
+        }
+    }
+
+    #[test]
+    fn test_find_frame_overflow() {
+        let mut framer = Framer::new().with_max_frame_len(8);
+        let mut encoder = Encoder::new();
+
+        // A frame of 6 bytes plus Address, Control and FCS fields is too long.
+        let mut messages: Vec<u8> = encoder.encode(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+        // Line noise without any Flag Sequence does not grow the frame forever.
+        messages.append(&mut vec![0x55; 100]);
+        messages.push(FLAG);
+        messages.append(&mut encoder.encode(&[0x01, 0x02, 0x03, 0x04]));
+
+        let frames: Vec<FramerEvent> = messages
+            .into_iter()
+            .filter_map(|byte| framer.find_frame(byte))
+            .collect();
+        assert_eq!(frames[0], FramerEvent::Overflow);
+        assert_eq!(frames[1], FramerEvent::Overflow);
+        assert_eq!(frames[2], FramerEvent::Frame(vec![0x01, 0x02, 0x03, 0x04]));
+        assert_eq!(frames.len(), 3);
+        assert_eq!(framer.overflows(), 2);
+    }
+
+    #[test]
+    fn test_decode_frame_at_max_frame_len() {
+        let mut framer = Framer::<BytesMut>::default()
+            .with_max_frame_len(8)
+            .with_accm(Accm::new(0x000a0000));
+        let mut encoder = Encoder::new();
+
+        // A 4 byte packet plus Address, Control and FCS fields is exactly 8
+        // bytes, an XON dropped before the closing Flag Sequence does not count.
+        let packet = [0x41, 0x42, 0x43, 0x44];
+        let mut encoded = encoder.encode(&packet);
+        encoded.insert(encoded.len() - 1, 0x11);
+        let frame = || FramerEvent::Frame(BytesMut::from(&packet[..]));
+
+        let frames: Vec<FramerEvent<BytesMut>> = encoded
+            .iter()
+            .filter_map(|byte| framer.find_frame(*byte))
+            .collect();
+        assert_eq!(frames, vec![frame()]);
+
+        let raw = &encoded[1..encoded.len() - 1];
+        assert_eq!(framer.decode_frame(raw), frame());
+        assert_eq!(framer.decode_frame_in_place(BytesMut::from(raw)), frame());
+        assert_eq!(framer.overflows(), 0);
+
+        // One byte more overflows on every path.
+        let mut encoded = encoder.encode(&[0x41, 0x42, 0x43, 0x44, 0x45]);
+        encoded.insert(0, FLAG);
+        let frames: Vec<FramerEvent<BytesMut>> = encoded
+            .iter()
+            .filter_map(|byte| framer.find_frame(*byte))
+            .collect();
+        assert_eq!(frames, vec![FramerEvent::Overflow]);
+
+        let raw = &encoded[1..encoded.len() - 1];
+        assert_eq!(framer.decode_frame(raw), FramerEvent::Overflow);
+        assert_eq!(
+            framer.decode_frame_in_place(BytesMut::from(raw)),
+            FramerEvent::Overflow
+        );
+        assert_eq!(framer.overflows(), 3);
+    }
+
+    #[test]
+    fn test_find_frame_array_buffer() {
+        let mut framer = Framer::<ArrayBuffer<8>>::default().with_pfc(true);
+        assert_eq!(framer.max_frame_len(), 8);
+        let mut encoder = Encoder::new().with_pfc(true);
+
+        // Address, Control and FCS fields take 4 bytes of the buffer, leaving
+        // 4 for the packet with its compressed Protocol field. The Protocol
+        // field is expanded again in the room left by the Control field.
+        let mut messages = encoder.encode(&[0x00, 0x21, 0x45, 0x46, 0x47]);
+        messages.append(&mut encoder.encode(&[0x00, 0x21, 0x45, 0x46, 0x47, 0x48]));
+
+        let frames: Vec<FramerEvent<ArrayBuffer<8>>> = messages
+            .into_iter()
+            .filter_map(|byte| framer.find_frame(byte))
+            .collect();
+        match &frames[0] {
+            FramerEvent::Frame(frame) => {
+                assert_eq!(frame.as_ref(), [0x00, 0x21, 0x45, 0x46, 0x47])
+            }
+            event => panic!("Framer failed with: {:?}", event),
+        }
+        assert_eq!(frames[1], FramerEvent::Overflow);
+        assert_eq!(frames.len(), 2);
+    }
+
+    #[test]
+    fn test_find_frame_abort() {
+        let mut messages: Vec<u8> = vec![0x7e, 0x01, 0x02, 0x7d, 0x7e];
+        messages.append(&mut vec![0x01, 0x02, 0x03, 0x05, 0x18, 0x28, 0x7e]);
+        messages.append(&mut vec![0x01, 0x7d, 0x7d, 0x03, 0x05, 0x18, 0x28, 0x7e]);
+        messages.append(&mut vec![0x01, 0x02, 0x03, 0x05, 0x18, 0x28, 0x7e]);
+
+        let mut framer = Framer::new();
+        let frames: Vec<FramerEvent> = messages
+            .into_iter()
+            .filter_map(|byte| framer.find_frame(byte))
+            .collect();
+        assert_eq!(frames[0], FramerEvent::Abort);
+        assert_eq!(frames[1], FramerEvent::Frame(vec![0x01, 0x02, 0x03, 0x05]));
+        assert_eq!(frames[2], FramerEvent::InvalidEscape);
+        assert_eq!(frames[3], FramerEvent::Frame(vec![0x01, 0x02, 0x03, 0x05]));
+        assert_eq!(frames.len(), 4);
+    }
+
+    #[test]
+    fn test_decode_frame_abort() {
+        let mut framer = Framer::new();
+        assert_eq!(framer.decode_frame(&[0x01, 0x02, 0x7d]), FramerEvent::Abort);
+        assert_eq!(
+            framer.decode_frame(&[0x01, 0x7d, 0x7d, 0x03, 0x05, 0x18, 0x28]),
+            FramerEvent::InvalidEscape
+        );
+        assert_eq!(
+            framer.decode_frame(&[0x01, 0x02, 0x03, 0x05, 0x18, 0x28]),
+            FramerEvent::Frame(vec![0x01, 0x02, 0x03, 0x05])
+        );
+    }
+
+    #[test]
+    fn test_decode_frame_in_place() {
+        let mut framer = Framer::<BytesMut>::default().with_pfc(true);
+        let mut encoder = Encoder::new().with_pfc(true);
+
+        // Escaped bytes and a compressed Protocol field.
+        let packet = [0x00, 0x21, 0x7e, 0x7d, 0x03];
+        let encoded = encoder.encode(&packet);
+        let raw = BytesMut::from(&encoded[1..encoded.len() - 1]);
+        assert_eq!(
+            framer.decode_frame(&raw),
+            framer.decode_frame_in_place(raw.clone())
+        );
+        // The frame is left in the raw buffer, the zero byte of the Protocol
+        // field taking the place of the Control field.
+        let control = raw.as_ptr().wrapping_add(1);
+        match framer.decode_frame_in_place(raw) {
+            FramerEvent::Frame(frame) => {
+                assert_eq!(frame, packet[..]);
+                assert_eq!(frame.as_ptr(), control);
+            }
+            event => panic!("Framer failed with: {:?}", event),
+        }
+
+        assert_eq!(
+            framer.decode_frame_in_place(BytesMut::from(&[0x01, 0x02, 0x7d][..])),
+            FramerEvent::Abort
+        );
+    }
+
+    #[test]
+    fn test_accm() {
+        let accm = Accm::default();
+        assert!(accm.is_escaped(0x00));
+        assert!(accm.is_escaped(0x1f));
+        assert!(!accm.is_escaped(0x20));
+        assert!(accm.is_escaped(0x7d));
+        assert!(accm.is_escaped(0x7e));
+        assert!(accm.is_escaped(0xfd));
+        assert!(accm.is_escaped(0xfe));
+        assert!(!accm.is_escaped(0xff));
+
+        // The Flag Sequence and Control Escape are escaped whatever the map.
+        let accm = Accm::none();
+        assert!(!accm.is_escaped(0x00));
+        assert!(accm.is_escaped(0x7d));
+        assert!(accm.is_escaped(0x7e));
+
+        // XON and XOFF only.
+        let accm = Accm::new(0x000a0000).with_escaped(0x91).with_escaped(0x93);
+        assert!(accm.is_escaped(0x11));
+        assert!(accm.is_escaped(0x13));
+        assert!(!accm.is_escaped(0x12));
+        assert!(accm.is_escaped(0x91));
+        assert!(accm.is_escaped(0x93));
+        assert!(accm.is_dropped(0x11));
+        assert!(!accm.is_dropped(0x91));
+    }
+
+    #[test]
+    fn test_encode_accm() {
+        let packet: Vec<u8> = (0x00u8..=0xffu8).collect();
+
+        // With a zero ACCM only the Flag Sequence and Control Escape are escaped.
+        let mut encoder = Encoder::new().with_accm(Accm::none());
+        let encoded = encoder.encode(&packet);
+        assert_eq!(encoded[0..3], [FLAG, ADDRESS, CONTROL]);
+        assert_eq!(encoded[3..3 + 0x20], packet[0..0x20]);
+
+        let mut framer = Framer::new();
+        let frames: Vec<FramerEvent> = encoded
+            .into_iter()
+            .filter_map(|byte| framer.find_frame(byte))
+            .collect();
+        assert_eq!(frames, vec![FramerEvent::Frame(packet.clone())]);
+
+        // Control characters inserted by the DCE are dropped by the receiver.
+        let mut encoder = Encoder::new();
+        let mut encoded = encoder.encode(&packet);
+        encoded.insert(10, 0x11);
+        encoded.insert(21, 0x13);
+        let mut framer = Framer::new().with_accm(Accm::new(0x000a0000));
+        let frames: Vec<FramerEvent> = encoded
+            .into_iter()
+            .filter_map(|byte| framer.find_frame(byte))
+            .collect();
+        assert_eq!(frames, vec![FramerEvent::Frame(packet)]);
+    }
+
+    #[test]
+    fn test_accm_dropped_after_control_escape() {
+        // An XON inserted by the DCE between a Control Escape and the byte it
+        // escapes, as in 7d 11 21.
+        let packet = [0x01, 0x7e, 0x02];
+        let mut encoder = Encoder::new();
+        let mut encoded = encoder.encode(&packet);
+        let escape = encoded
+            .windows(2)
+            .position(|pair| pair == [CONTROL_ESCAPE, 0x5e])
+            .unwrap();
+        encoded.insert(escape + 1, 0x11);
+
+        let mut framer = Framer::new().with_accm(Accm::new(0x000a0000));
+        let frames: Vec<FramerEvent> = encoded
+            .iter()
+            .filter_map(|byte| framer.find_frame(*byte))
+            .collect();
+        assert_eq!(frames, vec![FramerEvent::Frame(packet.to_vec())]);
+
+        let raw = &encoded[1..encoded.len() - 1];
+        assert_eq!(
+            framer.decode_frame(raw),
+            FramerEvent::Frame(packet.to_vec())
+        );
+    }
+
+    #[test]
+    fn test_encode_acfc_pfc() {
+        // An IPv4 packet, Protocol 0x0021.
+        let packet: Vec<u8> = vec![0x00, 0x21, 0x45, 0x00, 0x00, 0x14];
+
+        let mut encoder = Encoder::new()
+            .with_accm(Accm::none())
+            .with_acfc(true)
+            .with_pfc(true);
+        let encoded = encoder.encode(&packet);
+        assert_eq!(encoded[0..6], [FLAG, 0x21, 0x45, 0x00, 0x00, 0x14]);
+
+        let mut framer = Framer::new().with_pfc(true);
+        let frames: Vec<FramerEvent> = encoded
+            .iter()
+            .filter_map(|byte| framer.find_frame(*byte))
+            .collect();
+        assert_eq!(frames, vec![FramerEvent::Frame(packet.clone())]);
+
+        // Without PFC the receiver sees the compressed Protocol field as is.
+        let mut framer = Framer::new();
+        let frames: Vec<FramerEvent> = encoded
+            .iter()
+            .filter_map(|byte| framer.find_frame(*byte))
+            .collect();
+        assert_eq!(frames, vec![FramerEvent::Frame(packet[1..].to_vec())]);
+
+        // LCP, Protocol 0xc021, compresses neither its Protocol field nor
+        // the Address and Control fields.
+        let packet: Vec<u8> = vec![0xc0, 0x21, 0x01, 0x01, 0x00, 0x04];
+        let encoded = encoder.encode(&packet);
+        assert_eq!(encoded[0..4], [ADDRESS, CONTROL, 0xc0, 0x21]);
+        let frames: Vec<FramerEvent> = encoded
+            .iter()
+            .filter_map(|byte| framer.find_frame(*byte))
+            .collect();
+        assert_eq!(frames, vec![FramerEvent::Frame(packet)]);
+    }
+
+    #[test]
+    fn test_encode_fcs32() {
+        let packet: Vec<u8> = (0x00u8..=0xffu8).collect();
+
+        for acfc in [false, true] {
+            let mut encoder = Encoder::new()
+                .with_fcs_width(FcsWidth::Fcs32)
+                .with_acfc(acfc);
+            let encoded = encoder.encode(&packet);
+
+            let mut framer = Framer::new().with_fcs_width(FcsWidth::Fcs32);
+            let frames: Vec<FramerEvent> = encoded
+                .iter()
+                .filter_map(|byte| framer.find_frame(*byte))
+                .collect();
+            assert_eq!(frames, vec![FramerEvent::Frame(packet.clone())]);
+
+            // A 16-bit FCS receiver rejects the frame.
+            let mut framer = Framer::new();
+            let frames: Vec<FramerEvent> = encoded
+                .iter()
+                .filter_map(|byte| framer.find_frame(*byte))
+                .collect();
+            assert_eq!(frames, vec![FramerEvent::BadChecksum]);
+        }
+
+        let mut framer = Framer::new().with_fcs_width(FcsWidth::Fcs32);
+        assert_eq!(
+            framer.decode_frame(&[0x01, 0x02, 0x03]),
+            FramerEvent::InvalidFrame
+        );
     }
 }
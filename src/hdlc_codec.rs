@@ -0,0 +1,237 @@
+// A tokio_util codec for HDLC framing, so that a serial stream can be wrapped
+// in Framed and used as a Stream/Sink of frames.
+
+use bytes::{Bytes, BytesMut};
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::hdlc;
+use crate::hdlc::{Framer, FramerEvent, FLAG};
+
+// Frames are unescaped where they were read and yielded as Bytes sharing the
+// read buffer, without copying.
+pub struct HdlcCodec {
+    framer: Framer<BytesMut>,
+    encoder: hdlc::Encoder,
+    // True once a Flag Sequence has been seen, bytes before that are noise.
+    in_frame: bool,
+    // How far into the read buffer we have already searched for a Flag Sequence.
+    scanned: usize,
+}
+
+impl HdlcCodec {
+    pub fn new() -> Self {
+        HdlcCodec {
+            framer: Framer::default(),
+            encoder: hdlc::Encoder::new(),
+            in_frame: false,
+            scanned: 0,
+        }
+    }
+
+    // Use a Framer configured for the link, e.g. with its receiving ACCM.
+    pub fn with_framer(mut self, framer: Framer<BytesMut>) -> Self {
+        self.framer = framer;
+        self
+    }
+
+    // Use an Encoder configured for the link, e.g. with its sending ACCM.
+    pub fn with_encoder(mut self, encoder: hdlc::Encoder) -> Self {
+        self.encoder = encoder;
+        self
+    }
+}
+
+impl Default for HdlcCodec {
+    fn default() -> Self {
+        HdlcCodec::new()
+    }
+}
+
+impl Decoder for HdlcCodec {
+    type Item = FramerEvent<Bytes>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<FramerEvent<Bytes>>, io::Error> {
+        loop {
+            // Scan the new part of the buffer for the next Flag Sequence.
+            let pos = match src[self.scanned..].iter().position(|byte| *byte == FLAG) {
+                Some(pos) => self.scanned + pos,
+                None => {
+                    if !self.in_frame {
+                        src.clear();
+                    } else if src.len() > self.framer.max_frame_len().saturating_mul(2) {
+                        // Too long even if every byte were escaped, give up on
+                        // this frame and hunt for the next Flag Sequence.
+                        src.clear();
+                        self.in_frame = false;
+                        self.scanned = 0;
+                        return Ok(Some(self.framer.discard().map(BytesMut::freeze)));
+                    } else {
+                        self.scanned = src.len();
+                    }
+                    return Ok(None);
+                }
+            };
+
+            let mut raw = src.split_to(pos + 1);
+            self.scanned = 0;
+
+            if !self.in_frame {
+                self.in_frame = true;
+                continue;
+            }
+
+            // Consecutive Flag Sequences delimit empty frames, skip them.
+            if pos > 0 {
+                raw.truncate(pos);
+                let event = self.framer.decode_frame_in_place(raw);
+                return Ok(Some(event.map(BytesMut::freeze)));
+            }
+        }
+    }
+
+    // A stream ending mid-frame, e.g. a peer closing the connection or a
+    // capture cut short, drops the partial frame rather than failing.
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<FramerEvent<Bytes>>, io::Error> {
+        if let Some(event) = self.decode(src)? {
+            return Ok(Some(event));
+        }
+        src.clear();
+        self.in_frame = false;
+        self.scanned = 0;
+        Ok(None)
+    }
+}
+
+impl<T> Encoder<T> for HdlcCodec
+where
+    T: AsRef<[u8]>,
+{
+    type Error = io::Error;
+
+    fn encode(&mut self, packet: T, dst: &mut BytesMut) -> Result<(), io::Error> {
+        let frame = self.encoder.encode(packet.as_ref());
+        dst.extend_from_slice(&frame);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_stream::StreamExt;
+    use tokio_util::codec::FramedRead;
+
+    #[test]
+    fn test_decode_partial() {
+        let mut codec = HdlcCodec::new();
+
+        let mut encoded = BytesMut::new();
+        codec.encode(&[0x01u8, 0x7e, 0x03], &mut encoded).unwrap();
+        codec.encode(&[0x04u8, 0x05, 0x7d], &mut encoded).unwrap();
+
+        // Line noise before the first Flag Sequence is discarded.
+        let mut src = BytesMut::from(&[0x55u8, 0x55, 0x55][..]);
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        assert!(src.is_empty());
+
+        // Feed the frames in a few bytes at a time.
+        let mut frames: Vec<FramerEvent<Bytes>> = vec![];
+        for chunk in encoded.chunks(3) {
+            src.extend_from_slice(chunk);
+            while let Some(frame) = codec.decode(&mut src).unwrap() {
+                frames.push(frame);
+            }
+        }
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            frames[0],
+            FramerEvent::Frame(Bytes::from_static(&[0x01, 0x7e, 0x03]))
+        );
+        assert_eq!(
+            frames[1],
+            FramerEvent::Frame(Bytes::from_static(&[0x04, 0x05, 0x7d]))
+        );
+    }
+
+    #[test]
+    fn test_decode_bad_checksum() {
+        let mut codec = HdlcCodec::new();
+        let mut src = BytesMut::from(&[0x7eu8, 0x01, 0x02, 0x03, 0x05, 0x18, 0x29, 0x7e][..]);
+        assert_eq!(
+            codec.decode(&mut src).unwrap(),
+            Some(FramerEvent::BadChecksum)
+        );
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+    }
+
+    #[test]
+    fn test_decode_abort() {
+        let mut codec = HdlcCodec::new();
+        let mut src = BytesMut::from(&[0x7eu8, 0x01, 0x02, 0x7d, 0x7e][..]);
+        codec.encode(&[0x01u8, 0x02], &mut src).unwrap();
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(FramerEvent::Abort));
+        assert_eq!(
+            codec.decode(&mut src).unwrap(),
+            Some(FramerEvent::Frame(Bytes::from_static(&[0x01, 0x02])))
+        );
+    }
+
+    #[test]
+    fn test_decode_overflow() {
+        let mut codec = HdlcCodec::new().with_framer(Framer::default().with_max_frame_len(8));
+
+        let mut src = BytesMut::new();
+        codec.encode(&[0x01u8; 6], &mut src).unwrap();
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(FramerEvent::Overflow));
+
+        // Line noise without any Flag Sequence is not buffered forever.
+        src.extend_from_slice(&[0x7e; 1]);
+        src.extend_from_slice(&[0x55; 100]);
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(FramerEvent::Overflow));
+        assert!(src.is_empty());
+
+        // The tail of the broken frame is dropped up to the next Flag Sequence.
+        src.extend_from_slice(&[0x55, 0x55, 0x7e]);
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        codec.encode(&[0x01u8; 4], &mut src).unwrap();
+        assert_eq!(
+            codec.decode(&mut src).unwrap(),
+            Some(FramerEvent::Frame(Bytes::from(vec![0x01; 4])))
+        );
+        assert_eq!(codec.framer.overflows(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_framed_read() {
+        let mut encoder = hdlc::Encoder::new();
+        let mut encoded: Vec<u8> = vec![];
+        for n in 0u8..10 {
+            encoded.append(&mut encoder.encode(&[n; 100]));
+        }
+
+        let mut frames = FramedRead::new(encoded.as_slice(), HdlcCodec::new());
+        for n in 0u8..10 {
+            let frame = frames.next().await.unwrap().unwrap();
+            assert_eq!(frame, FramerEvent::Frame(Bytes::from(vec![n; 100])));
+        }
+        assert!(frames.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_framed_read_truncated() {
+        let mut encoder = hdlc::Encoder::new();
+        let mut encoded = encoder.encode(&[0x01, 0x02, 0x03]);
+        // The stream ends in the middle of the next frame.
+        encoded.extend_from_slice(&[0x41, 0x42]);
+
+        let mut frames = FramedRead::new(encoded.as_slice(), HdlcCodec::new());
+        let frame = frames.next().await.unwrap().unwrap();
+        assert_eq!(
+            frame,
+            FramerEvent::Frame(Bytes::from_static(&[0x01, 0x02, 0x03]))
+        );
+        assert!(frames.next().await.is_none());
+    }
+}
@@ -1,110 +1,104 @@
-extern crate core;
-use anyhow::Result;
+// HDLC framing with the C code in hdlc.c. Without the "c-hdlc" feature the
+// same API is implemented in Rust on top of the hdlc module, so that callers
+// build without a C toolchain.
 
-use libc::{off_t, size_t, ssize_t};
+use std::error::Error;
+use std::fmt;
 
-extern "C" {
-    fn init_hdlc();
-    /*
-     * Wraps a PPP packet into an HDLC frame and write it to a buffer.
-     *
-     * @param[out] frame    The buffer to store the encoded frame.
-     * @param[in]  frmsize  The output buffer size.
-     * @param[in]  packet   The buffer containing the packet.
-     * @param[in]  pktsize  The input packet size.
-     * @return              the number of bytes written to the buffer (i.e. the
-     *                      HDLC-encoded frame length) or ERR_HDLC_BUFFER_TOO_SMALL
-     *                      if the output buffer is too small
-     *
-     *   ssize_t hdlc_encode(uint8_t *frame, size_t frmsize,
-     *       const uint8_t *packet, size_t pktsize)
-     */
-    fn hdlc_encode(frame: *mut u8, frmsize: size_t, packet: *const u8, pktsize: size_t) -> ssize_t;
+#[cfg(feature = "c-hdlc")]
+mod c;
+#[cfg(feature = "c-hdlc")]
+use c::find_frame;
+#[cfg(feature = "c-hdlc")]
+pub use c::{hdlc_decode_ffi, hdlc_encode_ffi, init_hdlc_ffi, HdlcEncoder};
 
-    /*
-     * Finds the first frame in a buffer, starting search at start.
-     *
-     * @param[in]     buffer   The input buffer.
-     * @param[in]     bufsize  The input buffer size.
-     * @param[in,out] start    Offset of the beginning of the first frame in the buffer.
-     * @return                 the length of the first frame or ERR_HDLC_NO_FRAME_FOUND
-     *                         if no frame is found.
-     *
-     *    ssize_t hdlc_find_frame(const uint8_t *buffer, size_t bufsize, off_t *start)
-     */
-    fn hdlc_find_frame(buffer: *const u8, bufsize: size_t, start: *mut off_t) -> ssize_t;
+#[cfg(not(feature = "c-hdlc"))]
+mod fallback;
+#[cfg(not(feature = "c-hdlc"))]
+use fallback::find_frame;
+#[cfg(not(feature = "c-hdlc"))]
+pub use fallback::{hdlc_decode_ffi, hdlc_encode_ffi, init_hdlc_ffi, HdlcEncoder};
 
-    /*
-     * Extracts the first PPP packet found in the input buffer.
-     *
-     * The frame should be passed without its surrounding Flag Sequence (0x7e) bytes.
-     *
-     * @param[in]  frame    The buffer containing the encoded frame.
-     * @param[in]  frmsize  The input buffer size.
-     * @param[out] packet   The buffer to store the decoded packet.
-     * @param[in]  pktsize  The output packet buffer size.
-     * @return              the number of bytes written to the output packet
-     *                      buffer, or < 0 in case of error.
-     */
-    fn hdlc_decode(frame: *const u8, frmsize: size_t, packet: *mut u8, pktsize: size_t) -> ssize_t;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HdlcError {
+    BufferTooSmall,
+    NoFrameFound,
+    InvalidFrame,
+    BadChecksum,
+    // An error code the C code returned that hdlc.h does not define.
+    Unknown(isize),
 }
 
-pub fn init_hdlc_ffi() {
-    unsafe {
-        init_hdlc();
+impl Error for HdlcError {}
+
+impl fmt::Display for HdlcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HdlcError::BufferTooSmall => write!(f, "HdlcError: buffer too small"),
+            HdlcError::NoFrameFound => write!(f, "HdlcError: no frame found"),
+            HdlcError::InvalidFrame => write!(f, "HdlcError: invalid frame"),
+            HdlcError::BadChecksum => write!(f, "HdlcError: bad checksum"),
+            HdlcError::Unknown(code) => write!(f, "HdlcError: unknown error code {code}"),
+        }
     }
 }
 
-pub fn hdlc_encode_ffi(packet: &[u8]) -> Result<Vec<u8>, isize> {
-    let estimated_encoded_size = 9 + 2 * (packet.len());
-    let mut encoded: Vec<u8> = vec![0; estimated_encoded_size];
-
-    let p_encoded = encoded.as_mut_ptr();
-    let p_packet = packet.as_ptr();
-    let len = unsafe { hdlc_encode(p_encoded, encoded.len() as size_t, p_packet, packet.len()) };
-    if len < 0 {
-        Err(len)
-    } else {
-        encoded.truncate(len as usize);
-        Ok(encoded)
+pub fn hdlc_find_frame_ffi(buffer: &[u8]) -> Result<&[u8], HdlcError> {
+    match hdlc_frames_ffi(buffer).next() {
+        Some((_, frame)) => Ok(frame),
+        None => Err(HdlcError::NoFrameFound),
     }
 }
 
-pub fn hdlc_find_frame_ffi(buffer: &[u8]) -> Result<&[u8], isize> {
-    let mut offset: i64 = 0;
-    let p_offset = &mut offset as *mut i64;
+/*
+ * Iterates over all the frames in a buffer, yielding the offset of each frame
+ * in the buffer along with the frame itself.
+ */
+pub fn hdlc_frames_ffi(buffer: &[u8]) -> FramesFfi<'_> {
+    FramesFfi { buffer, offset: 0 }
+}
 
-    let p_buffer = buffer.as_ptr();
-    let res = unsafe { hdlc_find_frame(p_buffer, buffer.len(), p_offset) };
-    if res < 0 {
-        Err(res)
-    } else {
-        Ok(&buffer[offset as usize..offset as usize + res as usize])
+pub struct FramesFfi<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> FramesFfi<'a> {
+    /*
+     * The bytes following the last frame found so far. This starts with the
+     * Flag Sequence that closed that frame, so more data can be appended to it
+     * and searched again.
+     */
+    pub fn remainder(&self) -> &'a [u8] {
+        &self.buffer[self.offset..]
     }
 }
 
-pub fn hdlc_decode_ffi(frame: &[u8], packet: &mut [u8]) -> Result<usize, isize> {
-    let p_frame = frame.as_ptr();
-    let p_packet = packet.as_mut_ptr();
-    let res = unsafe { hdlc_decode(p_frame, frame.len() as size_t, p_packet, packet.len()) };
-    if res < 0 {
-        Err(res)
-    } else {
-        Ok(res as usize)
+impl<'a> Iterator for FramesFfi<'a> {
+    type Item = (usize, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (start, len) = find_frame(self.buffer, self.offset)?;
+
+        // The Flag Sequence closing this frame opens the next one.
+        self.offset = start + len;
+        Some((start, &self.buffer[start..self.offset]))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hdlc::{Accm, Encoder};
+    use anyhow::Result;
 
     #[test]
     fn test_hdlc_encode_ffi() {
-        init_hdlc_ffi();
+        let mut encoder = HdlcEncoder::new();
 
         let buffer_in: Vec<u8> = vec![0x40, 0x41, 0x42, 0x7e, 0x44, 0x45, 0x46, 0x47, 0x48];
 
-        let encoded = hdlc_encode_ffi(&buffer_in).unwrap();
+        let encoded = hdlc_encode_ffi(&mut encoder, &buffer_in).unwrap();
         println!("encoded: {:x?}", encoded);
 
         let res = hdlc_find_frame_ffi(&encoded);
@@ -116,18 +110,16 @@ mod tests {
         };
 
         let mut buffer_out: Vec<u8> = vec![0; 256];
-        let size = hdlc_decode_ffi(&frame, &mut buffer_out).unwrap();
+        let size = hdlc_decode_ffi(frame, &mut buffer_out).unwrap();
         assert_eq!(buffer_out[0..size], buffer_in);
     }
     #[test]
     fn test_hdlc_find_frame_ffi() {
-        init_hdlc_ffi();
-
         let data: Vec<u8> = vec![0x40, 0x41, 0x42, 0x44, 0x45, 0x46, 0x47, 0x48];
         let res = hdlc_find_frame_ffi(&data);
         match res {
             Ok(_) => panic!(),
-            Err(e) => assert_eq!(e, -2),
+            Err(e) => assert_eq!(e, HdlcError::NoFrameFound),
         }
 
         let buffer_in: Vec<u8> = vec![
@@ -142,4 +134,169 @@ mod tests {
             Err(e) => panic!("{e}"),
         }
     }
+
+    #[test]
+    fn test_hdlc_encoder_per_link() {
+        let packet: Vec<u8> = vec![0x40, 0x41, 0x42];
+
+        let mut encoder_1 = HdlcEncoder::new();
+        let mut encoder_2 = HdlcEncoder::new();
+
+        // Encoding on one link does not affect the Flag Sequence of the other.
+        let encoded_1 = hdlc_encode_ffi(&mut encoder_1, &packet).unwrap();
+        let encoded_2 = hdlc_encode_ffi(&mut encoder_2, &packet).unwrap();
+        assert_eq!(encoded_1[0], 0x7e);
+        assert_eq!(encoded_2[0], 0x7e);
+
+        let encoded_1 = hdlc_encode_ffi(&mut encoder_1, &packet).unwrap();
+        assert_eq!(encoded_1[0], 0xff);
+
+        // Until the link is reset.
+        init_hdlc_ffi(&mut encoder_1);
+        let encoded_1 = hdlc_encode_ffi(&mut encoder_1, &packet).unwrap();
+        assert_eq!(encoded_1[0], 0x7e);
+    }
+
+    #[test]
+    fn test_hdlc_decode_ffi_errors() {
+        let mut encoder = HdlcEncoder::new();
+        let encoded = hdlc_encode_ffi(&mut encoder, &[0x40, 0x41, 0x42, 0x43]).unwrap();
+        let frame = hdlc_find_frame_ffi(&encoded).unwrap();
+
+        let mut buffer_out: Vec<u8> = vec![0; 4];
+        let res = hdlc_decode_ffi(frame, &mut buffer_out);
+        assert_eq!(res, Err(HdlcError::BufferTooSmall));
+
+        let mut buffer_out: Vec<u8> = vec![0; 256];
+        let res = hdlc_decode_ffi(&frame[..3], &mut buffer_out);
+        assert_eq!(res, Err(HdlcError::InvalidFrame));
+
+        let mut corrupted = frame.to_vec();
+        corrupted[4] ^= 0x01;
+        let res = hdlc_decode_ffi(&corrupted, &mut buffer_out);
+        assert_eq!(res, Err(HdlcError::BadChecksum));
+
+        // HdlcErrors can be passed around as anyhow errors and recovered.
+        let res: Result<usize> = hdlc_decode_ffi(&corrupted, &mut buffer_out).map_err(|e| e.into());
+        let e = res.unwrap_err();
+        assert_eq!(e.downcast_ref::<HdlcError>(), Some(&HdlcError::BadChecksum));
+        assert_eq!(e.to_string(), "HdlcError: bad checksum");
+    }
+
+    #[cfg(feature = "c-hdlc")]
+    #[test]
+    fn test_hdlc_error_from_code() {
+        assert_eq!(HdlcError::from_code(-4), HdlcError::BadChecksum);
+
+        // Codes hdlc.h does not define are reported rather than panicking.
+        let e = HdlcError::from_code(-42);
+        assert_eq!(e, HdlcError::Unknown(-42));
+        assert_eq!(e.to_string(), "HdlcError: unknown error code -42");
+    }
+
+    #[test]
+    fn test_hdlc_frames_ffi() {
+        let buffer_in: Vec<u8> = vec![
+            0x55, 0x7e, 0x40, 0x41, 0x7e, 0x7e, 0x42, 0x43, 0x44, 0x7e, 0x45, 0x7e, 0xaa, 0xaa,
+        ];
+        let mut frames = hdlc_frames_ffi(&buffer_in);
+        assert_eq!(frames.next(), Some((2, &buffer_in[2..4])));
+        assert_eq!(frames.next(), Some((6, &buffer_in[6..9])));
+        assert_eq!(frames.next(), Some((10, &buffer_in[10..11])));
+        assert_eq!(frames.next(), None);
+        assert_eq!(frames.remainder(), [0x7e, 0xaa, 0xaa]);
+
+        // Without any frame the whole buffer remains.
+        let mut frames = hdlc_frames_ffi(&buffer_in[..4]);
+        assert_eq!(frames.next(), None);
+        assert_eq!(frames.remainder(), &buffer_in[..4]);
+    }
+
+    #[test]
+    fn test_hdlc_frames_ffi_capture() {
+        let capture: &[u8] = include!("../radar_capture.cap");
+
+        // Everything between Flag Sequences, except the noise before the
+        // first one and the partial frame after the last one.
+        let mut expected: Vec<&[u8]> = capture.split(|byte| *byte == 0x7e).skip(1).collect();
+        let partial = expected.pop().unwrap();
+        expected.retain(|frame| !frame.is_empty());
+
+        let mut frames = hdlc_frames_ffi(capture);
+        let mut count = 0;
+        for (offset, frame) in frames.by_ref() {
+            assert_eq!(capture[offset - 1], 0x7e);
+            assert_eq!(frame, expected[count]);
+            count += 1;
+        }
+        assert_eq!(count, expected.len());
+        assert_eq!(frames.remainder()[1..], *partial);
+    }
+
+    #[test]
+    fn test_hdlc_decode_ffi_accm_after_control_escape() {
+        let packet: Vec<u8> = vec![0x01, 0x7e, 0x02];
+        let mut encoder = Encoder::new();
+        let mut encoded = encoder.encode(&packet);
+
+        // An XON inserted by the DCE inside the escaped Flag Sequence.
+        let escape = encoded
+            .windows(2)
+            .position(|pair| pair == [0x7d, 0x5e])
+            .unwrap();
+        encoded.insert(escape + 1, 0x11);
+        let frame = hdlc_find_frame_ffi(&encoded).unwrap();
+        let mut buffer_out: Vec<u8> = vec![0; 256];
+        let size = hdlc_decode_ffi(frame, &mut buffer_out).unwrap();
+        assert_eq!(buffer_out[0..size], packet);
+    }
+
+    #[test]
+    fn test_hdlc_decode_ffi_address_control() {
+        let packet: Vec<u8> = vec![0x00, 0x21, 0x45, 0x00, 0x00, 0x14];
+        let mut buffer_out: Vec<u8> = vec![0; 256];
+
+        // Control field not escaped, hdlc_decode drops any other unescaped
+        // control characters.
+        let mut encoder = Encoder::new().with_accm(Accm::new(!(1 << 0x03)));
+        let encoded = encoder.encode(&packet);
+        assert_eq!(encoded[1..3], [0xff, 0x03]);
+        let frame = hdlc_find_frame_ffi(&encoded).unwrap();
+        let size = hdlc_decode_ffi(frame, &mut buffer_out).unwrap();
+        assert_eq!(buffer_out[0..size], packet);
+
+        // Address and Control fields omitted.
+        let mut encoder = Encoder::new().with_acfc(true);
+        let encoded = encoder.encode(&packet);
+        let frame = hdlc_find_frame_ffi(&encoded).unwrap();
+        let size = hdlc_decode_ffi(frame, &mut buffer_out).unwrap();
+        assert_eq!(buffer_out[0..size], packet);
+    }
+
+    #[test]
+    fn test_hdlc_decode_ffi_buffer_size() {
+        let packet: Vec<u8> = vec![0x40, 0x41, 0x42, 0x43];
+
+        // hdlc_decode needs room for the FCS field, and for the Address and
+        // Control fields only when they are present.
+        for (acfc, headroom) in [(false, 4), (true, 2)] {
+            let mut encoder = Encoder::new().with_acfc(acfc);
+            let encoded = encoder.encode(&packet);
+            let frame = hdlc_find_frame_ffi(&encoded).unwrap();
+
+            let mut buffer_out: Vec<u8> = vec![0; packet.len() + headroom];
+            let size = hdlc_decode_ffi(frame, &mut buffer_out).unwrap();
+            assert_eq!(buffer_out[0..size], packet);
+
+            let mut buffer_out: Vec<u8> = vec![0; packet.len() + headroom - 1];
+            let res = hdlc_decode_ffi(frame, &mut buffer_out);
+            assert_eq!(res, Err(HdlcError::BufferTooSmall));
+
+            // The buffer is checked before the FCS.
+            let mut corrupted = frame.to_vec();
+            corrupted[frame.len() - 2] ^= 0x01;
+            let res = hdlc_decode_ffi(&corrupted, &mut buffer_out);
+            assert_eq!(res, Err(HdlcError::BufferTooSmall));
+        }
+    }
 }
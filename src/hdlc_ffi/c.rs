@@ -0,0 +1,155 @@
+// The C HDLC code in hdlc.c, behind the hdlc_ffi API.
+
+use libc::{c_int, off_t, size_t, ssize_t};
+
+use super::HdlcError;
+
+/*
+ * Error codes returned by the C HDLC functions, as defined in hdlc.h.
+ */
+const ERR_HDLC_BUFFER_TOO_SMALL: ssize_t = -1;
+const ERR_HDLC_NO_FRAME_FOUND: ssize_t = -2;
+const ERR_HDLC_INVALID_FRAME: ssize_t = -3;
+const ERR_HDLC_BAD_CHECKSUM: ssize_t = -4;
+
+impl HdlcError {
+    pub(super) fn from_code(code: ssize_t) -> Self {
+        match code {
+            ERR_HDLC_BUFFER_TOO_SMALL => HdlcError::BufferTooSmall,
+            ERR_HDLC_NO_FRAME_FOUND => HdlcError::NoFrameFound,
+            ERR_HDLC_INVALID_FRAME => HdlcError::InvalidFrame,
+            ERR_HDLC_BAD_CHECKSUM => HdlcError::BadChecksum,
+            _ => HdlcError::Unknown(code),
+        }
+    }
+}
+
+/*
+ * The per link encoder state, mirrors struct hdlc_encoder in hdlc.h.
+ */
+#[repr(C)]
+pub struct HdlcEncoder {
+    need_flag_sequence: c_int,
+}
+
+impl HdlcEncoder {
+    pub fn new() -> Self {
+        let mut encoder = HdlcEncoder {
+            need_flag_sequence: 0,
+        };
+        init_hdlc_ffi(&mut encoder);
+        encoder
+    }
+}
+
+impl Default for HdlcEncoder {
+    fn default() -> Self {
+        HdlcEncoder::new()
+    }
+}
+
+extern "C" {
+    fn init_hdlc(encoder: *mut HdlcEncoder);
+    /*
+     * Wraps a PPP packet into an HDLC frame and write it to a buffer.
+     *
+     * @param[in,out] encoder  The encoder state of the link.
+     * @param[out] frame    The buffer to store the encoded frame.
+     * @param[in]  frmsize  The output buffer size.
+     * @param[in]  packet   The buffer containing the packet.
+     * @param[in]  pktsize  The input packet size.
+     * @return              the number of bytes written to the buffer (i.e. the
+     *                      HDLC-encoded frame length) or ERR_HDLC_BUFFER_TOO_SMALL
+     *                      if the output buffer is too small
+     *
+     *   ssize_t hdlc_encode(struct hdlc_encoder *encoder, uint8_t *frame, size_t frmsize,
+     *       const uint8_t *packet, size_t pktsize)
+     */
+    fn hdlc_encode(
+        encoder: *mut HdlcEncoder,
+        frame: *mut u8,
+        frmsize: size_t,
+        packet: *const u8,
+        pktsize: size_t,
+    ) -> ssize_t;
+
+    /*
+     * Finds the first frame in a buffer, starting search at start.
+     *
+     * @param[in]     buffer   The input buffer.
+     * @param[in]     bufsize  The input buffer size.
+     * @param[in,out] start    Offset of the beginning of the first frame in the buffer.
+     * @return                 the length of the first frame or ERR_HDLC_NO_FRAME_FOUND
+     *                         if no frame is found.
+     *
+     *    ssize_t hdlc_find_frame(const uint8_t *buffer, size_t bufsize, off_t *start)
+     */
+    fn hdlc_find_frame(buffer: *const u8, bufsize: size_t, start: *mut off_t) -> ssize_t;
+
+    /*
+     * Extracts the first PPP packet found in the input buffer.
+     *
+     * The frame should be passed without its surrounding Flag Sequence (0x7e) bytes.
+     *
+     * @param[in]  frame    The buffer containing the encoded frame.
+     * @param[in]  frmsize  The input buffer size.
+     * @param[out] packet   The buffer to store the decoded packet.
+     * @param[in]  pktsize  The output packet buffer size.
+     * @return              the number of bytes written to the output packet
+     *                      buffer, or < 0 in case of error.
+     */
+    fn hdlc_decode(frame: *const u8, frmsize: size_t, packet: *mut u8, pktsize: size_t) -> ssize_t;
+}
+
+pub fn init_hdlc_ffi(encoder: &mut HdlcEncoder) {
+    unsafe {
+        init_hdlc(encoder);
+    }
+}
+
+pub fn hdlc_encode_ffi(encoder: &mut HdlcEncoder, packet: &[u8]) -> Result<Vec<u8>, HdlcError> {
+    let estimated_encoded_size = 9 + 2 * (packet.len());
+    let mut encoded: Vec<u8> = vec![0; estimated_encoded_size];
+
+    let p_encoded = encoded.as_mut_ptr();
+    let p_packet = packet.as_ptr();
+    let len = unsafe {
+        hdlc_encode(
+            encoder,
+            p_encoded,
+            encoded.len() as size_t,
+            p_packet,
+            packet.len(),
+        )
+    };
+    if len < 0 {
+        Err(HdlcError::from_code(len))
+    } else {
+        encoded.truncate(len as usize);
+        Ok(encoded)
+    }
+}
+
+// Finds the next frame from offset, returning its start and length.
+pub(super) fn find_frame(buffer: &[u8], offset: usize) -> Option<(usize, usize)> {
+    let mut start = offset as off_t;
+    let p_start = &mut start as *mut off_t;
+
+    let p_buffer = buffer.as_ptr();
+    let res = unsafe { hdlc_find_frame(p_buffer, buffer.len(), p_start) };
+    if res < 0 {
+        return None;
+    }
+    Some((start as usize, res as usize))
+}
+
+pub fn hdlc_decode_ffi(frame: &[u8], packet: &mut [u8]) -> Result<usize, HdlcError> {
+    let p_frame = frame.as_ptr();
+    let p_packet = packet.as_mut_ptr();
+    let res = unsafe { hdlc_decode(p_frame, frame.len() as size_t, p_packet, packet.len()) };
+    if res < 0 {
+        Err(HdlcError::from_code(res))
+    } else {
+        Ok(res as usize)
+    }
+}
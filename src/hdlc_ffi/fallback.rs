@@ -0,0 +1,78 @@
+// The hdlc_ffi API without the C code, built on the hdlc module configured
+// like hdlc.c: escaping and dropping all control characters, 16-bit FCS.
+
+use super::HdlcError;
+use crate::hdlc::{Accm, Encoder, Framer, FramerEvent, FLAG};
+
+// Frames shorter than this are rejected before unescaping, as in hdlc.c.
+const MIN_FRAME_LEN: usize = 5;
+
+// The per link encoder state.
+pub struct HdlcEncoder {
+    encoder: Encoder,
+}
+
+impl HdlcEncoder {
+    pub fn new() -> Self {
+        HdlcEncoder {
+            encoder: Encoder::new(),
+        }
+    }
+}
+
+impl Default for HdlcEncoder {
+    fn default() -> Self {
+        HdlcEncoder::new()
+    }
+}
+
+pub fn init_hdlc_ffi(encoder: &mut HdlcEncoder) {
+    encoder.encoder.reset();
+}
+
+pub fn hdlc_encode_ffi(encoder: &mut HdlcEncoder, packet: &[u8]) -> Result<Vec<u8>, HdlcError> {
+    Ok(encoder.encoder.encode(packet))
+}
+
+// Finds the next frame from offset, returning its start and length.
+pub(super) fn find_frame(buffer: &[u8], offset: usize) -> Option<(usize, usize)> {
+    let flag = offset
+        + buffer
+            .get(offset..)?
+            .iter()
+            .position(|byte| *byte == FLAG)?;
+
+    // Discard empty frames between consecutive Flag Sequences.
+    let start = flag + buffer[flag..].iter().position(|byte| *byte != FLAG)?;
+    let len = buffer[start..].iter().position(|byte| *byte == FLAG)?;
+    Some((start, len))
+}
+
+pub fn hdlc_decode_ffi(frame: &[u8], packet: &mut [u8]) -> Result<usize, HdlcError> {
+    if frame.len() < MIN_FRAME_LEN {
+        return Err(HdlcError::InvalidFrame);
+    }
+
+    let mut framer = Framer::new()
+        .with_accm(Accm::default())
+        .with_max_frame_len(usize::MAX);
+    let unescaped: Vec<u8> = match framer.unescape_frame(frame) {
+        Ok(unescaped) => unescaped,
+        Err(_) => return Err(HdlcError::InvalidFrame),
+    };
+
+    // hdlc.c unescapes the whole frame into the packet buffer, before checking
+    // the FCS then stripping it along with any Address and Control fields.
+    // So it needs room for those fields on top of the packet.
+    if packet.len() < unescaped.len() {
+        return Err(HdlcError::BufferTooSmall);
+    }
+    match framer.check_frame(unescaped) {
+        FramerEvent::Frame(decoded) => {
+            packet[..decoded.len()].copy_from_slice(&decoded);
+            Ok(decoded.len())
+        }
+        FramerEvent::BadChecksum => Err(HdlcError::BadChecksum),
+        _ => Err(HdlcError::InvalidFrame),
+    }
+}
@@ -0,0 +1,300 @@
+// A loopback integrity test. Sequence-numbered frames with known payloads are
+// sent over a link whose far end sends them straight back, e.g. a serial port
+// with TX wired to RX, pty:// or mem://, and what comes back is checked for
+// lost, corrupted, duplicated and reordered frames, along with the round trip
+// latency.
+
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::select;
+use tokio::time::{sleep, timeout};
+use tokio_stream::StreamExt;
+use tokio_util::codec::FramedRead;
+
+use crate::hdlc::{Encoder, FramerEvent};
+use crate::hdlc_codec::HdlcCodec;
+use crate::transport::Transport;
+
+// A sequence number, the send time in microseconds since the test started,
+// then a pattern filling the rest of the payload.
+const PAYLOAD_LEN: usize = 64;
+const HEADER_LEN: usize = 12;
+
+// How long to wait for frames still in flight once all have been sent.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopbackOptions {
+    pub frames: u32,
+    // The pause between sending frames.
+    pub interval: Duration,
+}
+
+// The pattern depends on the sequence number, so a frame whose sequence
+// number was corrupted does not match. It runs through all byte values, so
+// Flag Sequences and Control Escapes in the payload get escaped.
+fn pattern_byte(seq: u32, i: usize) -> u8 {
+    (seq as u8).wrapping_mul(31).wrapping_add(i as u8)
+}
+
+pub fn payload(seq: u32, sent_at: Duration) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(PAYLOAD_LEN);
+    payload.extend_from_slice(&seq.to_be_bytes());
+    payload.extend_from_slice(&(sent_at.as_micros() as u64).to_be_bytes());
+    payload.extend((HEADER_LEN..PAYLOAD_LEN).map(|i| pattern_byte(seq, i)));
+    payload
+}
+
+// The sequence number and send time of an intact payload.
+fn parse_payload(payload: &[u8]) -> Option<(u32, Duration)> {
+    if payload.len() != PAYLOAD_LEN {
+        return None;
+    }
+    let seq = u32::from_be_bytes(payload[0..4].try_into().ok()?);
+    let sent_at = u64::from_be_bytes(payload[4..HEADER_LEN].try_into().ok()?);
+    let intact = payload[HEADER_LEN..]
+        .iter()
+        .enumerate()
+        .all(|(i, byte)| *byte == pattern_byte(seq, HEADER_LEN + i));
+    intact.then_some((seq, Duration::from_micros(sent_at)))
+}
+
+// Keeps count of what came back.
+#[derive(Default)]
+pub struct Tracker {
+    seen: HashSet<u32>,
+    highest: Option<u32>,
+    corrupted: u64,
+    duplicated: u64,
+    reordered: u64,
+    latencies: Vec<Duration>,
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Tracker::default()
+    }
+
+    // Records a framer event at a time since the test started.
+    pub fn record<B: AsRef<[u8]>>(&mut self, event: FramerEvent<B>, now: Duration) {
+        let frame = match event {
+            FramerEvent::Frame(frame) => frame,
+            // The FCS or the framing caught the corruption.
+            _ => {
+                self.corrupted += 1;
+                return;
+            }
+        };
+        let Some((seq, sent_at)) = parse_payload(frame.as_ref()) else {
+            self.corrupted += 1;
+            return;
+        };
+        if !self.seen.insert(seq) {
+            self.duplicated += 1;
+            return;
+        }
+        match self.highest {
+            Some(highest) if seq < highest => self.reordered += 1,
+            _ => self.highest = Some(seq),
+        }
+        self.latencies.push(now.saturating_sub(sent_at));
+    }
+
+    // The number of distinct frames received intact.
+    pub fn received(&self) -> u64 {
+        self.seen.len() as u64
+    }
+
+    pub fn report(&self, sent: u64) -> IntegrityReport {
+        let mut latencies = self.latencies.clone();
+        latencies.sort();
+        IntegrityReport {
+            sent,
+            received: self.received(),
+            lost: sent.saturating_sub(self.received()),
+            corrupted: self.corrupted,
+            duplicated: self.duplicated,
+            reordered: self.reordered,
+            latency_p50: percentile(&latencies, 50),
+            latency_p90: percentile(&latencies, 90),
+            latency_p99: percentile(&latencies, 99),
+            latency_max: latencies.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+// The nearest rank percentile of sorted values.
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (percent * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityReport {
+    pub sent: u64,
+    pub received: u64,
+    pub lost: u64,
+    pub corrupted: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+    pub latency_p50: Duration,
+    pub latency_p90: Duration,
+    pub latency_p99: Duration,
+    pub latency_max: Duration,
+}
+
+impl IntegrityReport {
+    pub fn passed(&self) -> bool {
+        self.lost == 0 && self.corrupted == 0 && self.duplicated == 0 && self.reordered == 0
+    }
+}
+
+impl fmt::Display for IntegrityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Sent:       {}", self.sent)?;
+        writeln!(f, "Received:   {}", self.received)?;
+        writeln!(f, "Lost:       {}", self.lost)?;
+        writeln!(f, "Corrupted:  {}", self.corrupted)?;
+        writeln!(f, "Duplicated: {}", self.duplicated)?;
+        writeln!(f, "Reordered:  {}", self.reordered)?;
+        writeln!(
+            f,
+            "Latency:    p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+            self.latency_p50, self.latency_p90, self.latency_p99, self.latency_max
+        )?;
+        write!(f, "{}", if self.passed() { "PASSED" } else { "FAILED" })
+    }
+}
+
+// Runs the test over an open transport.
+pub async fn loopback_test<T: Transport>(
+    transport: T,
+    options: &LoopbackOptions,
+) -> Result<IntegrityReport> {
+    let (read_half, mut write_half) = transport.split();
+    let mut frames = FramedRead::new(read_half, HdlcCodec::new());
+    let mut tracker = Tracker::new();
+    let start = Instant::now();
+
+    let sender = async {
+        let mut encoder = Encoder::new();
+        for seq in 0..options.frames {
+            let frame = encoder.encode(&payload(seq, start.elapsed()));
+            write_half
+                .write_all(&frame)
+                .await
+                .context("Error on writing")?;
+            sleep(options.interval).await;
+        }
+        Ok::<(), anyhow::Error>(())
+    };
+
+    {
+        let receiver = async {
+            while let Some(event) = frames.next().await {
+                tracker.record(event.context("Error on read")?, start.elapsed());
+                if tracker.received() == options.frames as u64 {
+                    break;
+                }
+            }
+            Ok::<(), anyhow::Error>(())
+        };
+        tokio::pin!(sender);
+        tokio::pin!(receiver);
+
+        select! {
+            res = &mut receiver => res?,
+            res = &mut sender => {
+                res?;
+                if let Ok(res) = timeout(DRAIN_TIMEOUT, &mut receiver).await {
+                    res?;
+                }
+            }
+        }
+    }
+
+    Ok(tracker.report(options.frames as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{MemoryLoopback, SerialSettings};
+
+    fn frame(seq: u32, sent_at_ms: u64) -> FramerEvent {
+        FramerEvent::Frame(payload(seq, Duration::from_millis(sent_at_ms)))
+    }
+
+    #[test]
+    fn test_payload() {
+        let payload = payload(7, Duration::from_micros(1234));
+        assert_eq!(payload.len(), PAYLOAD_LEN);
+        assert_eq!(
+            parse_payload(&payload),
+            Some((7, Duration::from_micros(1234)))
+        );
+
+        let mut corrupted = payload.clone();
+        corrupted[PAYLOAD_LEN - 1] ^= 0x01;
+        assert_eq!(parse_payload(&corrupted), None);
+
+        // The pattern belongs to the sequence number.
+        let mut corrupted = payload.clone();
+        corrupted[3] = 8;
+        assert_eq!(parse_payload(&corrupted), None);
+        assert_eq!(parse_payload(&payload[1..]), None);
+    }
+
+    #[test]
+    fn test_tracker() {
+        let mut tracker = Tracker::new();
+        let now = Duration::from_millis(10);
+        tracker.record(frame(0, 9), now);
+        tracker.record(frame(2, 8), now);
+        // Late, so reordered.
+        tracker.record(frame(1, 7), now);
+        tracker.record(frame(2, 6), now);
+        tracker.record(FramerEvent::<Vec<u8>>::BadChecksum, now);
+        tracker.record(FramerEvent::Frame(vec![0x00; PAYLOAD_LEN]), now);
+        // 3 never came back.
+
+        let report = tracker.report(5);
+        assert_eq!(report.received, 3);
+        assert_eq!(report.lost, 2);
+        assert_eq!(report.corrupted, 2);
+        assert_eq!(report.duplicated, 1);
+        assert_eq!(report.reordered, 1);
+        assert_eq!(report.latency_p50, Duration::from_millis(2));
+        assert_eq!(report.latency_max, Duration::from_millis(3));
+        assert!(!report.passed());
+    }
+
+    #[test]
+    fn test_percentile() {
+        let sorted: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&sorted, 50), Duration::from_millis(50));
+        assert_eq!(percentile(&sorted, 99), Duration::from_millis(99));
+        assert_eq!(percentile(&sorted[..1], 99), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_loopback_test() {
+        let transport = MemoryLoopback::open("", &SerialSettings::default())
+            .await
+            .unwrap();
+        let options = LoopbackOptions {
+            frames: 100,
+            interval: Duration::ZERO,
+        };
+        let report = loopback_test(transport, &options).await.unwrap();
+        assert!(report.passed(), "{report}");
+        assert_eq!(report.received, 100);
+    }
+}
@@ -0,0 +1,34 @@
+// HDLC framing and CRCs for our serial links, plus the serial port test driven
+// by the tokio_serial binary. The C HDLC code and protobuf support are behind
+// the "c-hdlc" and "protobuf" features, hdlc_ffi falls back to Rust without
+// the C code. The binary's own dependencies are behind the "cli" feature.
+//
+// Without the "std" feature, on by default, only crc, frame_buffer and hdlc are built,
+// under no_std with alloc, for the microcontroller side of the link.
+
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+extern crate alloc;
+
+pub mod crc;
+pub mod frame_buffer;
+#[cfg(feature = "std")]
+pub mod gray_code;
+pub mod hdlc;
+#[cfg(feature = "std")]
+pub mod hdlc_codec;
+#[cfg(feature = "std")]
+pub mod hdlc_ffi;
+#[cfg(feature = "std")]
+pub mod integrity;
+#[cfg(feature = "protobuf")]
+pub mod protobuf_experiment;
+#[cfg(feature = "std")]
+pub mod reconnect;
+#[cfg(feature = "std")]
+pub mod serial_port_test;
+#[cfg(feature = "std")]
+pub mod transport;
+
+#[cfg(all(test, feature = "c-hdlc"))]
+mod hdlc_differential;
@@ -1,15 +1,14 @@
 use clap::Parser;
 use log::{debug, error};
+use std::time::Duration;
+use tokio_serial::{DataBits, FlowControl, Parity, StopBits};
 
-mod protobuf_experiment;
-
-mod serial_port_test;
-use serial_port_test::{list_serial_ports, serial_port_test};
-
-mod crc;
-mod gray_code;
-mod hdlc;
-mod hdlc_ffi;
+use serial_link::integrity::LoopbackOptions;
+use serial_link::reconnect::ReconnectPolicy;
+use serial_link::serial_port_test::{list_serial_ports, serial_loopback_test, serial_port_test};
+use serial_link::transport::{
+    parse_data_bits, parse_flow_control, parse_parity, parse_stop_bits, SerialSettings,
+};
 
 /// Simple program to test a serial ports
 #[derive(Parser, Debug)]
@@ -19,55 +18,54 @@ struct Args {
     #[arg(short, long, default_value_t = 921600)]
     baud_rate: u32,
 
-    /// Serial port
+    /// Serial port, or tcp://host:port, unix:///path, pty:// or mem://
     #[arg(short, long, default_value = "/dev/ttyUSB0")]
     port: String,
 
-    /// List serial ports
-    #[arg(short, long, default_value_t = false)]
-    list: bool,
-}
+    /// Data bits: 5, 6, 7 or 8
+    #[arg(long, default_value = "8", value_parser = parse_data_bits)]
+    data_bits: DataBits,
 
-use crc::*;
-use gray_code::*;
-use hdlc::*;
-use hdlc_ffi::*;
+    /// Parity: none, odd or even
+    #[arg(long, default_value = "none", value_parser = parse_parity)]
+    parity: Parity,
 
-use std::ffi::CString;
-use std::os::raw::c_char;
+    /// Stop bits: 1 or 2
+    #[arg(long, default_value = "1", value_parser = parse_stop_bits)]
+    stop_bits: StopBits,
 
-fn test_protobuf() {
-    protobuf_experiment::protobuf_experiment();
-}
+    /// Flow control: none, software (XON/XOFF) or hardware (RTS/CTS)
+    #[arg(long, default_value = "none", value_parser = parse_flow_control)]
+    flow_control: FlowControl,
 
-fn test_gray_code() {
-    // An exerise in single track gray codes.
-    single_track_gray_code();
-}
+    /// Reopen the port when nothing is read from it for this many milliseconds
+    #[arg(long, default_value_t = 1000)]
+    timeout_ms: u64,
 
-fn test_hdlc() {
-    // Build a "message" containing all possible byte values.
-    let mut data: Vec<u8> = vec![];
-    for byte in 0x00u8..=0xFFu8 {
-        data.push(byte);
-    }
-    println!("Data in: {:x?}", data);
+    /// Give up after this many retries without the port staying open, 0 to never give up
+    #[arg(long, default_value_t = 0)]
+    max_attempts: u32,
 
-    init_hdlc_ffi();
+    /// Delay before retrying to open the port, doubled on each failed retry
+    #[arg(long, default_value_t = 100)]
+    retry_delay_ms: u64,
 
-    let encoded = hdlc_encode_ffi(&data).unwrap();
-    println!("Encoded: {:x?}", encoded);
+    /// Longest delay between attempts to open the port
+    #[arg(long, default_value_t = 30000)]
+    max_retry_delay_ms: u64,
 
-    let mut framer = Framer::new();
-    for byte in encoded {
-        if let Some(frame) = framer.find_frame(byte) {
-            println!("Data out: {:x?}", &frame);
+    /// Send this many numbered frames, check that they come back intact and
+    /// report the results, for a port with TX wired to RX
+    #[arg(long)]
+    loopback_test: Option<u32>,
 
-            let crc = crc(0xffff, &frame);
-            println!("CRC: {:x?}", crc);
-            assert_eq!(crc, 0xf0b8);
-        }
-    }
+    /// Pause between frames in the loopback test, in milliseconds
+    #[arg(long, default_value_t = 10)]
+    interval_ms: u64,
+
+    /// List serial ports
+    #[arg(short, long, default_value_t = false)]
+    list: bool,
 }
 
 #[tokio::main]
@@ -83,7 +81,42 @@ async fn main() {
             error!("{e:?}");
         }
     } else {
-        let res = serial_port_test(&args.port, args.baud_rate).await;
+        let settings = SerialSettings {
+            baud_rate: args.baud_rate,
+            data_bits: args.data_bits,
+            parity: args.parity,
+            stop_bits: args.stop_bits,
+            flow_control: args.flow_control,
+            timeout: Duration::from_millis(args.timeout_ms),
+        };
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(args.retry_delay_ms),
+            max_delay: Duration::from_millis(args.max_retry_delay_ms),
+            max_attempts: (args.max_attempts > 0).then_some(args.max_attempts),
+            ..ReconnectPolicy::default()
+        };
+
+        if let Some(frames) = args.loopback_test {
+            let options = LoopbackOptions {
+                frames,
+                interval: Duration::from_millis(args.interval_ms),
+            };
+            match serial_loopback_test(&args.port, settings, options).await {
+                Ok(report) => {
+                    println!("{report}");
+                    if !report.passed() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    error!("serial_loopback_test failed with: {e:?}");
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+
+        let res = serial_port_test(&args.port, settings, policy).await;
         error!("serial_port_test failed with: {:?}", res);
     }
 }
@@ -1,3 +1,6 @@
+// The generated code allows lints that newer compilers have removed.
+#![allow(renamed_and_removed_lints)]
+
 use log::{error, info};
 use protobuf::{EnumOrUnknown, Message};
 include!(concat!(env!("OUT_DIR"), "/protos/mod.rs"));
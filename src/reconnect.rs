@@ -0,0 +1,368 @@
+// Reopening a link after it fails. Attempts back off exponentially with some
+// jitter, and are given up after a maximum number if one is set.
+//
+// A USB serial port is followed by the identity of the device behind it, its
+// VID, PID and serial number, so that it is found again after being unplugged
+// and replugged, even under another device name, e.g. /dev/ttyUSB1 rather
+// than /dev/ttyUSB0.
+
+use anyhow::Result;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+use tokio::task::spawn_blocking;
+use tokio::time::sleep;
+use tokio_serial::{SerialPortInfo, SerialPortType};
+
+// How often available ports are listed to notice a USB port being unplugged.
+const HOTPLUG_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Listing the ports enumerates udev or sysfs, which blocks, so it is done off
+// the runtime rather than stall the tasks running the link.
+async fn available_ports() -> Result<Vec<SerialPortInfo>> {
+    Ok(spawn_blocking(tokio_serial::available_ports).await??)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    // Up to this fraction of each delay is taken off at random, so that links
+    // restarted together do not retry in lockstep.
+    pub jitter: f64,
+    // Retries after consecutive failed attempts before giving up, or None to
+    // keep trying.
+    pub max_attempts: Option<u32>,
+    // Once a link has stayed up this long, a later drop starts the retries
+    // over from the initial delay.
+    pub reset_after: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.5,
+            max_attempts: None,
+            reset_after: Duration::from_secs(10),
+        }
+    }
+}
+
+pub struct Backoff {
+    policy: ReconnectPolicy,
+    attempts: u32,
+    random: u64,
+}
+
+impl Backoff {
+    pub fn new(policy: ReconnectPolicy) -> Self {
+        // Jitter needs no better randomness than the clock.
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|time| time.subsec_nanos())
+            .unwrap_or_default();
+        Backoff::with_seed(policy, nanos as u64)
+    }
+
+    pub fn with_seed(policy: ReconnectPolicy, seed: u64) -> Self {
+        Backoff {
+            policy,
+            attempts: 0,
+            // Xorshift gets stuck at 0.
+            random: seed | 1,
+        }
+    }
+
+    // The number of retries since the last success.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    // How long to wait after another failed attempt, or None to give up.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if let Some(max_attempts) = self.policy.max_attempts {
+            if self.attempts >= max_attempts {
+                return None;
+            }
+        }
+        let delay = self
+            .policy
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(self.attempts))
+            .min(self.policy.max_delay);
+        self.attempts += 1;
+
+        let cut = delay.mul_f64(self.policy.jitter.clamp(0.0, 1.0) * self.next_fraction());
+        Some(delay - cut)
+    }
+
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+
+    // A number in [0, 1) from a xorshift generator.
+    fn next_fraction(&mut self) -> f64 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        (self.random >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// What identifies a USB serial device across being unplugged and replugged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbDevice {
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_number: Option<String>,
+}
+
+impl UsbDevice {
+    // The USB device behind a serial port, if it is a USB serial port.
+    pub fn at(path: &str, ports: &[SerialPortInfo]) -> Option<UsbDevice> {
+        ports
+            .iter()
+            .find(|port| port.port_name == path)
+            .and_then(|port| match &port.port_type {
+                SerialPortType::UsbPort(info) => Some(UsbDevice {
+                    vid: info.vid,
+                    pid: info.pid,
+                    serial_number: info.serial_number.clone(),
+                }),
+                _ => None,
+            })
+    }
+
+    // Where the device is now. Without a serial number another device with
+    // the same VID and PID may match, so the last known path is preferred.
+    pub fn find<'a>(&self, last_path: &str, ports: &'a [SerialPortInfo]) -> Option<&'a str> {
+        let mut found = ports
+            .iter()
+            .filter(|port| UsbDevice::at(&port.port_name, ports).as_ref() == Some(self))
+            .map(|port| port.port_name.as_str());
+        let first = found.next()?;
+        if first == last_path {
+            return Some(first);
+        }
+        Some(found.find(|path| *path == last_path).unwrap_or(first))
+    }
+}
+
+impl fmt::Display for UsbDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "USB device {:04x}:{:04x}", self.vid, self.pid)?;
+        if let Some(serial_number) = &self.serial_number {
+            write!(f, " serial number {serial_number}")?;
+        }
+        Ok(())
+    }
+}
+
+// The address a link is opened at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Fixed(String),
+    // A serial port whose USB device, if it has one, is not known yet.
+    Serial(String),
+    Usb { device: UsbDevice, path: String },
+}
+
+impl Target {
+    // Follows the USB device behind a serial port, if it is plugged in now.
+    pub async fn serial(path: &str) -> Target {
+        let ports = available_ports().await.unwrap_or_default();
+        let mut target = Target::Serial(path.to_string());
+        target.identify(&ports);
+        target
+    }
+
+    // Follows the USB device behind a serial port once it is in the ports.
+    pub fn identify(&mut self, ports: &[SerialPortInfo]) {
+        let Target::Serial(path) = self else {
+            return;
+        };
+        if let Some(device) = UsbDevice::at(path, ports) {
+            let path = std::mem::take(path);
+            *self = Target::Usb { device, path };
+        }
+    }
+
+    // Called once the link is open, so that a USB serial port that was
+    // unplugged at startup is followed from now on.
+    pub async fn opened(&mut self) {
+        if let Target::Serial(_) = self {
+            if let Ok(ports) = available_ports().await {
+                self.identify(&ports);
+            }
+        }
+    }
+
+    // Where to open the link now, or None while a USB device is unplugged.
+    pub async fn locate(&mut self) -> Result<Option<String>> {
+        match self {
+            Target::Fixed(address) | Target::Serial(address) => Ok(Some(address.clone())),
+            Target::Usb { device, path } => {
+                let ports = available_ports().await?;
+                let Some(found) = device.find(path, &ports) else {
+                    return Ok(None);
+                };
+                *path = found.to_string();
+                Ok(Some(path.clone()))
+            }
+        }
+    }
+
+    // Completes once a USB device is no longer where the link was opened,
+    // never for other targets.
+    pub async fn unplugged(&self) {
+        let Target::Usb { device, path } = self else {
+            return std::future::pending().await;
+        };
+        loop {
+            sleep(HOTPLUG_POLL_INTERVAL).await;
+            // Failing to list the ports does not mean the device is gone.
+            let Ok(ports) = available_ports().await else {
+                continue;
+            };
+            if device.find(path, &ports) != Some(path.as_str()) {
+                return;
+            }
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Fixed(address) | Target::Serial(address) => write!(f, "{address}"),
+            Target::Usb { device, path } => write!(f, "{path} ({device})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_serial::UsbPortInfo;
+
+    fn usb_port(port_name: &str, pid: u16, serial_number: Option<&str>) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: port_name.to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid: 0x0403,
+                pid,
+                serial_number: serial_number.map(str::to_string),
+                manufacturer: None,
+                product: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            jitter: 0.0,
+            max_attempts: Some(6),
+            reset_after: Duration::from_secs(10),
+        };
+        let mut backoff = Backoff::new(policy);
+        let delays: Vec<u128> = std::iter::from_fn(|| backoff.next_delay())
+            .map(|delay| delay.as_millis())
+            .collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(backoff.attempts(), 6);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn test_backoff_jitter() {
+        let policy = ReconnectPolicy {
+            jitter: 0.5,
+            ..ReconnectPolicy::default()
+        };
+        let mut backoff = Backoff::with_seed(policy, 42);
+        let mut delays = vec![];
+        for _ in 0..100 {
+            backoff.reset();
+            let delay = backoff.next_delay().unwrap();
+            assert!(delay > Duration::from_millis(50) && delay <= Duration::from_millis(100));
+            delays.push(delay);
+        }
+        delays.dedup();
+        assert!(delays.len() > 1);
+
+        // Long waits are not overflowed.
+        for _ in 0..100 {
+            assert!(backoff.next_delay().unwrap() <= policy.max_delay);
+        }
+    }
+
+    #[test]
+    fn test_usb_device_find() {
+        let ports = vec![
+            usb_port("/dev/ttyUSB0", 0x6001, Some("A1")),
+            usb_port("/dev/ttyUSB1", 0x6001, Some("B2")),
+        ];
+        let device = UsbDevice::at("/dev/ttyUSB1", &ports).unwrap();
+        assert_eq!(device.serial_number.as_deref(), Some("B2"));
+        assert_eq!(device.to_string(), "USB device 0403:6001 serial number B2");
+        assert_eq!(device.find("/dev/ttyUSB1", &ports), Some("/dev/ttyUSB1"));
+
+        // Unplugged.
+        assert_eq!(device.find("/dev/ttyUSB1", &ports[..1]), None);
+
+        // Replugged under another name.
+        let ports = vec![
+            usb_port("/dev/ttyUSB0", 0x6001, Some("A1")),
+            usb_port("/dev/ttyUSB2", 0x6001, Some("B2")),
+        ];
+        assert_eq!(device.find("/dev/ttyUSB1", &ports), Some("/dev/ttyUSB2"));
+
+        // Without serial numbers the last known path is preferred.
+        let ports = vec![
+            usb_port("/dev/ttyUSB0", 0x6015, None),
+            usb_port("/dev/ttyUSB1", 0x6015, None),
+        ];
+        let device = UsbDevice::at("/dev/ttyUSB1", &ports).unwrap();
+        assert_eq!(device.find("/dev/ttyUSB1", &ports), Some("/dev/ttyUSB1"));
+        assert_eq!(device.find("/dev/ttyUSB3", &ports), Some("/dev/ttyUSB0"));
+    }
+
+    #[tokio::test]
+    async fn test_target_identify() {
+        // Unplugged at startup, the serial port is opened by its path.
+        let mut target = Target::Serial("/dev/ttyUSB1".to_string());
+        target.identify(&[]);
+        assert_eq!(target, Target::Serial("/dev/ttyUSB1".to_string()));
+        assert_eq!(
+            target.locate().await.unwrap().as_deref(),
+            Some("/dev/ttyUSB1")
+        );
+
+        // Once plugged in its USB device is followed.
+        let ports = vec![
+            usb_port("/dev/ttyUSB0", 0x6001, Some("A1")),
+            usb_port("/dev/ttyUSB1", 0x6001, Some("B2")),
+        ];
+        target.identify(&ports);
+        let Target::Usb { device, path } = &target else {
+            panic!("Expected a USB target, got: {target:?}");
+        };
+        assert_eq!(device.serial_number.as_deref(), Some("B2"));
+        assert_eq!(path, "/dev/ttyUSB1");
+        assert_eq!(
+            target.to_string(),
+            "/dev/ttyUSB1 (USB device 0403:6001 serial number B2)"
+        );
+
+        // Other targets are left alone.
+        let mut target = Target::Fixed("/dev/ttyUSB1".to_string());
+        target.identify(&ports);
+        assert_eq!(target, Target::Fixed("/dev/ttyUSB1".to_string()));
+    }
+}
@@ -1,155 +1,250 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
 use log::Level::Info;
 use log::{debug, error, info, log_enabled};
-use std::time::{Duration, SystemTime};
-use tokio::io::{
-    split, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf,
-};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tokio::select;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::mpsc::Sender;
-use tokio::time::sleep;
+use tokio::time::{sleep, timeout};
+use tokio_serial::SerialPortType;
 use tokio_serial::SerialStream;
-use tokio_serial::{FlowControl, SerialPortType};
+use tokio_stream::StreamExt;
+use tokio_util::codec::FramedRead;
 
-use crate::hdlc;
 use crate::hdlc::*;
+use crate::hdlc_codec::HdlcCodec;
+use crate::integrity::{loopback_test, IntegrityReport, LoopbackOptions};
+use crate::reconnect::{Backoff, ReconnectPolicy, Target};
+#[cfg(unix)]
+use crate::transport::PtyLoopback;
+use crate::transport::{MemoryLoopback, Port, SerialSettings, Transport};
 
 #[derive(Debug, Clone)]
-enum Msg {
-    Line(String),
-    Buf(Vec<u8>),
+pub enum Msg {
+    Buf(Bytes),
 }
 
-///////// Custom error type ///////////
-use std::error::Error;
-use std::fmt;
-
-#[derive(Debug)]
-struct WtfError;
-
-impl Error for WtfError {}
-
-impl fmt::Display for WtfError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "WtfError: WTF!")
-    }
-}
-// Note: This is how to make a WtfError:
-//     let r: Result<()> = Result::Err(WtfError {}.into());
-//
-//////////////
-
-fn epoch_seconds() -> Result<u64> {
+pub fn epoch_seconds() -> Result<u64> {
     Ok(SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)?
         .as_secs())
 }
 
-async fn writer(mut writer: impl tokio::io::AsyncWrite + Unpin) -> Result<()> {
-    let msg: &[u8] = include!("../radar_capture.cap");
+// The radar capture holds escaped frames without an FCS. Returns their
+// packets, so that they can be encoded again with an FCS the Framer accepts.
+pub fn capture_packets() -> Vec<Vec<u8>> {
+    let capture: &[u8] = include!("../radar_capture.cap");
 
-    loop {
-        println!("Writing:\n{:x?}", msg);
-        writer.write(msg).await.context("Error on writing")?;
-        sleep(Duration::from_millis(100)).await;
-    }
+    capture
+        .split(|byte| *byte == FLAG)
+        .filter(|frame| !frame.is_empty())
+        .map(|frame| {
+            let mut escaped = false;
+            let mut packet = Vec::with_capacity(frame.len());
+            for &byte in frame {
+                if byte == CONTROL_ESCAPE {
+                    escaped = true;
+                } else {
+                    packet.push(if escaped { byte ^ 0x20 } else { byte });
+                    escaped = false;
+                }
+            }
+            packet
+        })
+        .collect()
 }
 
-async fn line_reader(reader: ReadHalf<SerialStream>, tx: Sender<Msg>) -> Result<()> {
-    let buf_reader: BufReader<ReadHalf<SerialStream>> = BufReader::new(reader);
+pub async fn writer(
+    mut writer: impl tokio::io::AsyncWrite + Unpin,
+    encoder: &mut Encoder,
+) -> Result<()> {
+    let packets = capture_packets();
 
-    let mut lines: tokio::io::Lines<BufReader<ReadHalf<SerialStream>>> = buf_reader.lines();
     loop {
-        match lines.next_line().await.context("Error on reading line")? {
-            Some(line) => {
-                //info!("Serial got: {:?}", line);
-                tx.send(Msg::Line(line)).await?;
-            }
-            None => error!("Serial got: empty line."),
+        for packet in &packets {
+            let frame = encoder.encode(packet);
+            debug!("Writing:\n{:x?}", frame);
+            writer.write_all(&frame).await.context("Error on writing")?;
         }
+        sleep(Duration::from_millis(100)).await;
     }
 }
 
-async fn frame_reader(
-    mut reader: impl tokio::io::AsyncRead + Unpin,
+// Gives up with an error once nothing has come from the reader for the read
+// timeout, so that a silent link is reopened.
+pub async fn frame_reader(
+    reader: impl tokio::io::AsyncRead + Unpin,
     tx: Sender<Msg>,
+    read_timeout: Duration,
 ) -> Result<()> {
-    let mut buf: [u8; 1] = [0];
-    let mut framer = hdlc::Framer::new();
+    let mut frames = FramedRead::new(reader, HdlcCodec::new());
     loop {
-        reader.read(&mut buf).await.context("Error on read")?;
-        let byte = buf[0];
-        if let Some(frame) = framer.find_frame(byte) {
-            tx.send(Msg::Buf(frame.clone())).await?;
+        let Ok(next) = timeout(read_timeout, frames.next()).await else {
+            bail!("Nothing read for {} ms", read_timeout.as_millis());
+        };
+        match next {
+            Some(Ok(FramerEvent::Frame(frame))) => tx.send(Msg::Buf(frame)).await?,
+            Some(Ok(event)) => debug!("Dropped frame: {:?}", event),
+            Some(Err(e)) => return Err(e).context("Error on read"),
+            None => return Ok(()),
         }
     }
 }
 
-async fn printer(mut rx: Receiver<Msg>) {
-    loop {
-        let msg: Option<Msg> = rx.recv().await;
-        if let Some(msg) = msg {
-            match msg {
-                Msg::Line(line) => info!("{}", line),
-                Msg::Buf(buf) => info!("{:?}", buf),
-            }
+// Returns the number of messages printed once all senders are gone.
+pub async fn printer(mut rx: Receiver<Msg>) -> usize {
+    let mut count = 0;
+    while let Some(msg) = rx.recv().await {
+        match msg {
+            Msg::Buf(buf) => info!("{:?}", buf),
         }
+        count += 1;
     }
+    count
 }
 
-fn open_serial(
-    path: String,
-    baud_rate: u32,
-) -> Result<(ReadHalf<SerialStream>, WriteHalf<SerialStream>)> {
-    let port_builder: tokio_serial::SerialPortBuilder =
-        tokio_serial::new(path.clone(), baud_rate).flow_control(FlowControl::None);
+async fn test_serial(
+    port: String,
+    settings: SerialSettings,
+    policy: ReconnectPolicy,
+) -> Result<()> {
+    let port: Port = port.parse()?;
+    settings.validate()?;
+    println!("Using port: {port} at {settings}.");
 
-    let stream: SerialStream =
-        SerialStream::open(&port_builder).context(format!("Failed to open serial port {path}"))?;
-    Ok(split(stream))
+    let fixed = |address: &str| Target::Fixed(address.to_string());
+    match &port {
+        Port::Serial(path) => {
+            test_link::<SerialStream>(Target::serial(path).await, &settings, policy).await
+        }
+        Port::Tcp(address) => test_link::<TcpStream>(fixed(address), &settings, policy).await,
+        #[cfg(unix)]
+        Port::Unix(path) => test_link::<UnixStream>(fixed(path), &settings, policy).await,
+        #[cfg(unix)]
+        Port::Pty => test_link::<PtyLoopback>(fixed(""), &settings, policy).await,
+        Port::Memory => test_link::<MemoryLoopback>(fixed(""), &settings, policy).await,
+    }
 }
 
-async fn test_serial(path: String, baud_rate: u32) -> Result<()> {
-    println!("Using serial port: {path} at {baud_rate} baud.");
+async fn test_link<T: Transport>(
+    mut target: Target,
+    settings: &SerialSettings,
+    policy: ReconnectPolicy,
+) -> Result<()> {
+    // One HDLC encoder per link, reset each time the link is opened.
+    let mut encoder = Encoder::new();
+    let mut backoff = Backoff::new(policy);
+    // Only log an error again once it changes.
+    let mut last_error = String::new();
 
     loop {
         let (tx, rx) = mpsc::channel(32);
 
-        let res = open_serial(path.clone(), baud_rate);
-        match res {
-            Ok(serial_stream_halves) => {
-                let (read_half, write_half) = serial_stream_halves;
-                let reader = BufReader::new(read_half);
+        let res = match target.locate().await {
+            Ok(Some(address)) => T::open(&address, settings).await,
+            Ok(None) => Err(anyhow!("{target} is unplugged")),
+            Err(e) => Err(e.context(format!("Failed to locate {target}"))),
+        };
+        let e = match res {
+            Ok(transport) => {
+                last_error.clear();
+                target.opened().await;
+                println!("Opened {}.", transport.describe());
+                let (read_half, write_half) = transport.split();
+
+                // A freshly opened port needs a Flag Sequence before the first frame.
+                encoder.reset();
+
+                let opened_at = Instant::now();
+                let e = select! {
+                    val = writer(write_half, &mut encoder) => anyhow!("writer completed with: {val:?}"),
 
-                select! {
-                    val = writer(write_half) => error!("writer completed with: {val:?}"),
+                    val = frame_reader(read_half, tx, settings.timeout) => anyhow!("reader completed with: {val:?}"),
 
-                    val = frame_reader(reader, tx) => error!("reader completed with: {val:?}"),
+                    _ = printer(rx) => anyhow!("printer completed"),
 
-                    _ = printer(rx) => {}
+                    _ = target.unplugged() => anyhow!("{target} was unplugged"),
+                };
+
+                // Only back off afresh once the link has proved stable, so
+                // that one failing as soon as it is opened is given up on.
+                if opened_at.elapsed() >= policy.reset_after {
+                    backoff.reset();
                 }
+                e
             }
-            Err(e) => {
-                error!("{:?}", e);
-                sleep(Duration::from_millis(1000)).await;
-            }
+            Err(e) => e,
+        };
+
+        let Some(delay) = backoff.next_delay() else {
+            let retries = backoff.attempts();
+            return Err(e.context(format!("Gave up after {retries} retries")));
+        };
+        let message = format!("{e:?}");
+        if message != last_error {
+            error!("{message}");
+        } else {
+            debug!("Retry {} failed again", backoff.attempts());
         }
+        last_error = message;
+        debug!("Retrying in {delay:?}");
+        sleep(delay).await;
     }
 }
 
-use libc::size_t;
-
-pub async fn serial_port_test(port: &str, baud_rate: u32) -> Result<()> {
+pub async fn serial_port_test(
+    port: &str,
+    settings: SerialSettings,
+    policy: ReconnectPolicy,
+) -> Result<()> {
     select! {
-        res = test_serial(port.to_string(), baud_rate) => {
+        res = test_serial(port.to_string(), settings, policy) => {
             debug!("{:?}", res);
             res
         }
     }
 }
 
+// Runs the loopback integrity test once, without reconnecting.
+pub async fn serial_loopback_test(
+    port: &str,
+    settings: SerialSettings,
+    options: LoopbackOptions,
+) -> Result<IntegrityReport> {
+    let port: Port = port.parse()?;
+    settings.validate()?;
+    println!(
+        "Loopback test of {} frames on port: {port} at {settings}.",
+        options.frames
+    );
+
+    match &port {
+        Port::Serial(path) => run_loopback_test::<SerialStream>(path, &settings, &options).await,
+        Port::Tcp(address) => run_loopback_test::<TcpStream>(address, &settings, &options).await,
+        #[cfg(unix)]
+        Port::Unix(path) => run_loopback_test::<UnixStream>(path, &settings, &options).await,
+        #[cfg(unix)]
+        Port::Pty => run_loopback_test::<PtyLoopback>("", &settings, &options).await,
+        Port::Memory => run_loopback_test::<MemoryLoopback>("", &settings, &options).await,
+    }
+}
+
+async fn run_loopback_test<T: Transport>(
+    address: &str,
+    settings: &SerialSettings,
+    options: &LoopbackOptions,
+) -> Result<IntegrityReport> {
+    let transport = T::open(address, settings).await?;
+    println!("Opened {}.", transport.describe());
+    loopback_test(transport, options).await
+}
+
 pub fn list_serial_ports() -> Result<()> {
     println!("Available serial ports:");
 
@@ -194,4 +289,36 @@ mod tests {
 
         assert_eq!(epoch_seconds().unwrap(), now);
     }
+
+    #[tokio::test]
+    async fn test_frame_reader_timeout() {
+        // The other end stays open but sends nothing.
+        let (_writer, reader) = tokio::io::duplex(64);
+        let (tx, _rx) = mpsc::channel(32);
+        let res = frame_reader(reader, tx, Duration::from_millis(50)).await;
+        assert_eq!(res.unwrap_err().to_string(), "Nothing read for 50 ms");
+    }
+
+    #[tokio::test]
+    async fn test_link_drops_count_as_retries() {
+        // The reader times out while the writer pauses between runs of the
+        // capture, so the link drops soon after each time it is opened.
+        let settings = SerialSettings {
+            timeout: Duration::from_millis(20),
+            ..SerialSettings::default()
+        };
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+            max_attempts: Some(2),
+            ..ReconnectPolicy::default()
+        };
+        let res = timeout(
+            Duration::from_secs(10),
+            serial_port_test("mem://", settings, policy),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.unwrap_err().to_string(), "Gave up after 2 retries");
+    }
 }
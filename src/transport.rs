@@ -0,0 +1,556 @@
+// The byte streams a link can run over. Besides serial ports, a link can go
+// over TCP, e.g. to ser2net on another machine, a Unix socket, or one of two
+// loopbacks, over a pty pair or in memory, for testing without hardware.
+//
+// A transport is picked with a URL-like port string:
+//   /dev/ttyUSB0 or serial:///dev/ttyUSB0   a serial port
+//   tcp://host:port                         a TCP socket
+//   unix:///path/to/socket                  a Unix socket
+//   pty://                                  a loopback over a pty pair
+//   mem://                                  an in-memory loopback
+//
+// Unix sockets and pty pairs are only available on Unix.
+
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+use tokio::io::{duplex, split, AsyncRead, AsyncWrite, DuplexStream, ReadBuf, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio_serial::{DataBits, FlowControl, Parity, SerialPort, SerialStream, StopBits};
+
+// How much an in-memory loopback buffers before writes wait for reads.
+const MEMORY_LOOPBACK_SIZE: usize = 64 * 1024;
+
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin + Sized + 'static {
+    // Opens the transport at the address part of a port string. Transports
+    // other than serial ports ignore the serial line settings.
+    fn open(address: &str, settings: &SerialSettings) -> impl Future<Output = Result<Self>> + Send;
+
+    // Changes the serial line settings of an open transport, if it has them.
+    fn reconfigure(&mut self, _settings: &SerialSettings) -> Result<()> {
+        Ok(())
+    }
+
+    // Splits the transport so reading and writing can be separate tasks.
+    fn split(self) -> (ReadHalf<Self>, WriteHalf<Self>) {
+        split(self)
+    }
+
+    // What the transport is connected to, for logging.
+    fn describe(&self) -> String;
+}
+
+// A parsed port string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Port {
+    Serial(String),
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(String),
+    #[cfg(unix)]
+    Pty,
+    Memory,
+}
+
+impl FromStr for Port {
+    type Err = anyhow::Error;
+
+    fn from_str(port: &str) -> Result<Self> {
+        // Plain paths are serial ports.
+        let Some((scheme, address)) = port.split_once("://") else {
+            return Ok(Port::Serial(port.to_string()));
+        };
+        let needs_address = |address: &str| {
+            if address.is_empty() {
+                bail!("Missing address in port {port}");
+            }
+            Ok(address.to_string())
+        };
+        match scheme {
+            "serial" => Ok(Port::Serial(needs_address(address)?)),
+            "tcp" => Ok(Port::Tcp(needs_address(address)?)),
+            #[cfg(unix)]
+            "unix" => Ok(Port::Unix(needs_address(address)?)),
+            #[cfg(unix)]
+            "pty" => Ok(Port::Pty),
+            "mem" => Ok(Port::Memory),
+            _ => bail!("Unknown transport {scheme} in port {port}"),
+        }
+    }
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Port::Serial(path) => write!(f, "serial://{path}"),
+            Port::Tcp(address) => write!(f, "tcp://{address}"),
+            #[cfg(unix)]
+            Port::Unix(path) => write!(f, "unix://{path}"),
+            #[cfg(unix)]
+            Port::Pty => write!(f, "pty://"),
+            Port::Memory => write!(f, "mem://"),
+        }
+    }
+}
+
+// Serial line settings, 8N1 without flow control by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialSettings {
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    pub flow_control: FlowControl,
+    // How long reading waits for data before the link is considered down.
+    pub timeout: Duration,
+}
+
+impl Default for SerialSettings {
+    fn default() -> Self {
+        SerialSettings {
+            baud_rate: 921600,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+            timeout: Duration::from_millis(1000),
+        }
+    }
+}
+
+impl SerialSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.baud_rate == 0 {
+            bail!("The baud rate must be above 0");
+        }
+        if self.timeout.is_zero() {
+            bail!("The timeout must be above 0 ms");
+        }
+        Ok(())
+    }
+
+    // The settings a serial port has once opened, the driver may not have
+    // taken all of those asked for, e.g. a pty ignores data bits and parity.
+    // Async streams report no timeout as their reads never block.
+    pub fn of(port: &impl SerialPort) -> Result<Self> {
+        Ok(SerialSettings {
+            baud_rate: port.baud_rate()?,
+            data_bits: port.data_bits()?,
+            parity: port.parity()?,
+            stop_bits: port.stop_bits()?,
+            flow_control: port.flow_control()?,
+            timeout: port.timeout(),
+        })
+    }
+}
+
+// E.g. "921600 baud 8N1, no flow control, 1000 ms timeout", leaving out a
+// zero timeout.
+impl fmt::Display for SerialSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let data_bits = match self.data_bits {
+            DataBits::Five => 5,
+            DataBits::Six => 6,
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+        };
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        let flow_control = match self.flow_control {
+            FlowControl::None => "no flow control",
+            FlowControl::Software => "XON/XOFF flow control",
+            FlowControl::Hardware => "RTS/CTS flow control",
+        };
+        write!(
+            f,
+            "{} baud {data_bits}{parity}{stop_bits}, {flow_control}",
+            self.baud_rate
+        )?;
+        if !self.timeout.is_zero() {
+            write!(f, ", {} ms timeout", self.timeout.as_millis())?;
+        }
+        Ok(())
+    }
+}
+
+// Parsers for the serial line settings on the command line.
+
+pub fn parse_data_bits(data_bits: &str) -> Result<DataBits> {
+    match data_bits {
+        "5" => Ok(DataBits::Five),
+        "6" => Ok(DataBits::Six),
+        "7" => Ok(DataBits::Seven),
+        "8" => Ok(DataBits::Eight),
+        _ => bail!("Data bits must be 5, 6, 7 or 8"),
+    }
+}
+
+pub fn parse_parity(parity: &str) -> Result<Parity> {
+    match parity.to_ascii_lowercase().as_str() {
+        "none" | "n" => Ok(Parity::None),
+        "odd" | "o" => Ok(Parity::Odd),
+        "even" | "e" => Ok(Parity::Even),
+        _ => bail!("Parity must be none, odd or even"),
+    }
+}
+
+pub fn parse_stop_bits(stop_bits: &str) -> Result<StopBits> {
+    match stop_bits {
+        "1" => Ok(StopBits::One),
+        "2" => Ok(StopBits::Two),
+        _ => bail!("Stop bits must be 1 or 2"),
+    }
+}
+
+pub fn parse_flow_control(flow_control: &str) -> Result<FlowControl> {
+    match flow_control.to_ascii_lowercase().as_str() {
+        "none" => Ok(FlowControl::None),
+        "software" | "xonxoff" => Ok(FlowControl::Software),
+        "hardware" | "rtscts" => Ok(FlowControl::Hardware),
+        _ => bail!("Flow control must be none, software (XON/XOFF) or hardware (RTS/CTS)"),
+    }
+}
+
+pub fn open_serial(path: &str, settings: &SerialSettings) -> Result<SerialStream> {
+    settings.validate()?;
+    let port_builder: tokio_serial::SerialPortBuilder = tokio_serial::new(path, settings.baud_rate)
+        .data_bits(settings.data_bits)
+        .parity(settings.parity)
+        .stop_bits(settings.stop_bits)
+        .flow_control(settings.flow_control)
+        .timeout(settings.timeout);
+
+    SerialStream::open(&port_builder).context(format!("Failed to open serial port {path}"))
+}
+
+impl Transport for SerialStream {
+    fn open(path: &str, settings: &SerialSettings) -> impl Future<Output = Result<Self>> + Send {
+        // Opening a serial port does not block.
+        let res = open_serial(path, settings);
+        async { res }
+    }
+
+    fn reconfigure(&mut self, settings: &SerialSettings) -> Result<()> {
+        settings.validate()?;
+        self.set_baud_rate(settings.baud_rate)?;
+        self.set_data_bits(settings.data_bits)?;
+        self.set_parity(settings.parity)?;
+        self.set_stop_bits(settings.stop_bits)?;
+        self.set_flow_control(settings.flow_control)?;
+        self.set_timeout(settings.timeout)?;
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        let name = self.name().unwrap_or_default();
+        match SerialSettings::of(self) {
+            Ok(settings) => format!("serial port {name} at {settings}"),
+            Err(_) => format!("serial port {name}"),
+        }
+    }
+}
+
+impl Transport for TcpStream {
+    fn open(
+        address: &str,
+        _settings: &SerialSettings,
+    ) -> impl Future<Output = Result<Self>> + Send {
+        let address = address.to_string();
+        async move {
+            let stream = TcpStream::connect(&address)
+                .await
+                .context(format!("Failed to connect to {address}"))?;
+            // Frames are small and latency matters more than throughput.
+            stream.set_nodelay(true)?;
+            Ok(stream)
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self.peer_addr() {
+            Ok(address) => format!("TCP socket to {address}"),
+            Err(_) => "TCP socket".to_string(),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixStream {
+    fn open(path: &str, _settings: &SerialSettings) -> impl Future<Output = Result<Self>> + Send {
+        let path = path.to_string();
+        async move {
+            UnixStream::connect(&path)
+                .await
+                .context(format!("Failed to connect to {path}"))
+        }
+    }
+
+    fn describe(&self) -> String {
+        let path = self
+            .peer_addr()
+            .ok()
+            .and_then(|address| address.as_pathname().map(|path| path.display().to_string()));
+        match path {
+            Some(path) => format!("Unix socket {path}"),
+            None => "Unix socket".to_string(),
+        }
+    }
+}
+
+// Reads back what was written, by writing to one end of a pair of streams and
+// reading from the other.
+pub struct Loopback<T> {
+    reader: T,
+    writer: T,
+}
+
+// What is written to the master end of the pty is read from the slave end,
+// which is in raw mode so the bytes pass through unchanged.
+#[cfg(unix)]
+pub type PtyLoopback = Loopback<SerialStream>;
+
+pub type MemoryLoopback = Loopback<DuplexStream>;
+
+#[cfg(unix)]
+impl Transport for PtyLoopback {
+    fn open(
+        _address: &str,
+        settings: &SerialSettings,
+    ) -> impl Future<Output = Result<Self>> + Send {
+        // The slave end takes the serial line settings like a real port.
+        let res = SerialStream::pair()
+            .context("Failed to open a pty pair")
+            .and_then(|(master, mut slave)| {
+                slave.reconfigure(settings)?;
+                Ok(Loopback {
+                    reader: slave,
+                    writer: master,
+                })
+            });
+        async { res }
+    }
+
+    fn reconfigure(&mut self, settings: &SerialSettings) -> Result<()> {
+        self.reader.reconfigure(settings)
+    }
+
+    fn describe(&self) -> String {
+        let name = self.reader.name().unwrap_or_default();
+        match SerialSettings::of(&self.reader) {
+            Ok(settings) => format!("pty loopback through {name} at {settings}"),
+            Err(_) => format!("pty loopback through {name}"),
+        }
+    }
+}
+
+impl Transport for MemoryLoopback {
+    fn open(
+        _address: &str,
+        _settings: &SerialSettings,
+    ) -> impl Future<Output = Result<Self>> + Send {
+        let (reader, writer) = duplex(MEMORY_LOOPBACK_SIZE);
+        async { Ok(Loopback { reader, writer }) }
+    }
+
+    fn describe(&self) -> String {
+        "in-memory loopback".to_string()
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Loopback<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.reader).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Loopback<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    #[cfg(unix)]
+    use tokio::net::UnixListener;
+
+    async fn check_round_trip<T: Transport>(transport: T) {
+        let (mut read_half, mut write_half) = transport.split();
+        write_half
+            .write_all(&[0x7e, 0x01, 0x02, 0x7e])
+            .await
+            .unwrap();
+        let mut buf = [0; 4];
+        read_half.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [0x7e, 0x01, 0x02, 0x7e]);
+    }
+
+    #[test]
+    fn test_port_from_str() {
+        let port: Port = "/dev/ttyUSB0".parse().unwrap();
+        assert_eq!(port, Port::Serial("/dev/ttyUSB0".to_string()));
+        let port: Port = "serial:///dev/ttyUSB0".parse().unwrap();
+        assert_eq!(port, Port::Serial("/dev/ttyUSB0".to_string()));
+        let port: Port = "tcp://localhost:2000".parse().unwrap();
+        assert_eq!(port, Port::Tcp("localhost:2000".to_string()));
+        #[cfg(unix)]
+        {
+            let port: Port = "unix:///tmp/link.sock".parse().unwrap();
+            assert_eq!(port, Port::Unix("/tmp/link.sock".to_string()));
+            assert_eq!("pty://".parse::<Port>().unwrap(), Port::Pty);
+        }
+        assert_eq!("mem://".parse::<Port>().unwrap(), Port::Memory);
+
+        // Port strings survive a round trip through Display.
+        let port: Port = "tcp://localhost:2000".parse().unwrap();
+        assert_eq!(port.to_string().parse::<Port>().unwrap(), port);
+
+        assert!("tcp://".parse::<Port>().is_err());
+        assert!("usb://1234".parse::<Port>().is_err());
+    }
+
+    #[test]
+    fn test_serial_settings() {
+        let settings = SerialSettings {
+            baud_rate: 9600,
+            data_bits: parse_data_bits("7").unwrap(),
+            parity: parse_parity("even").unwrap(),
+            stop_bits: parse_stop_bits("2").unwrap(),
+            flow_control: parse_flow_control("rtscts").unwrap(),
+            timeout: Duration::from_millis(500),
+        };
+        assert!(settings.validate().is_ok());
+        assert_eq!(
+            settings.to_string(),
+            "9600 baud 7E2, RTS/CTS flow control, 500 ms timeout"
+        );
+
+        assert!(parse_data_bits("9").is_err());
+        assert!(parse_parity("mark").is_err());
+        assert!(parse_stop_bits("1.5").is_err());
+        assert!(parse_flow_control("dtrdsr").is_err());
+
+        let settings = SerialSettings {
+            baud_rate: 0,
+            ..SerialSettings::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pty_settings() {
+        // The pty loopback takes serial line settings like a real port,
+        // except for data bits and parity.
+        let settings = SerialSettings {
+            baud_rate: 115200,
+            stop_bits: StopBits::Two,
+            ..SerialSettings::default()
+        };
+        let mut pty = PtyLoopback::open("", &settings).await.unwrap();
+        let describe = pty.describe();
+        assert!(
+            describe.ends_with("at 115200 baud 8N2, no flow control"),
+            "{describe}"
+        );
+
+        let settings = SerialSettings {
+            baud_rate: 9600,
+            ..settings
+        };
+        pty.reconfigure(&settings).unwrap();
+        let describe = pty.describe();
+        assert!(
+            describe.ends_with("at 9600 baud 8N2, no flow control"),
+            "{describe}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_loopbacks() {
+        check_round_trip(
+            MemoryLoopback::open("", &SerialSettings::default())
+                .await
+                .unwrap(),
+        )
+        .await;
+
+        #[cfg(unix)]
+        {
+            let pty = PtyLoopback::open("", &SerialSettings::default())
+                .await
+                .unwrap();
+            assert!(pty.describe().starts_with("pty loopback through /dev/pts/"));
+            check_round_trip(pty).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sockets() {
+        // Servers echoing back whatever they receive.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = stream.split();
+            tokio::io::copy(&mut reader, &mut writer).await.unwrap();
+        });
+        let tcp = TcpStream::open(&address, &SerialSettings::default())
+            .await
+            .unwrap();
+        assert_eq!(tcp.describe(), format!("TCP socket to {address}"));
+        check_round_trip(tcp).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket() {
+        let path = std::env::temp_dir().join(format!("transport-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = stream.split();
+            tokio::io::copy(&mut reader, &mut writer).await.unwrap();
+        });
+        let unix = UnixStream::open(path.to_str().unwrap(), &SerialSettings::default())
+            .await
+            .unwrap();
+        assert_eq!(unix.describe(), format!("Unix socket {}", path.display()));
+        check_round_trip(unix).await;
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+// Runs the serial port test tasks end to end over a pty loopback, in place of
+// a device on /dev/ttyUSB0.
+#![cfg(all(feature = "std", unix))]
+
+use std::time::Duration;
+
+use serial_link::hdlc::Encoder;
+use serial_link::integrity::LoopbackOptions;
+use serial_link::serial_port_test::{
+    capture_packets, frame_reader, printer, serial_loopback_test, writer, Msg,
+};
+use serial_link::transport::{PtyLoopback, SerialSettings, Transport};
+use tokio::select;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout};
+
+#[tokio::test]
+async fn test_pty_loopback_frames() {
+    let settings = SerialSettings::default();
+    let (read_half, write_half) = PtyLoopback::open("", &settings).await.unwrap().split();
+    let (tx, mut rx) = mpsc::channel(32);
+    let mut encoder = Encoder::new();
+
+    tokio::spawn(frame_reader(read_half, tx, settings.timeout));
+    tokio::spawn(async move { writer(write_half, &mut encoder).await });
+
+    // The captured packets arrive with an FCS that passes the check.
+    for packet in capture_packets().iter().take(3) {
+        let msg = timeout(Duration::from_secs(5), rx.recv()).await.unwrap();
+        match msg {
+            Some(Msg::Buf(frame)) => assert_eq!(frame[..], packet[..]),
+            msg => panic!("Expected a frame, got: {:?}", msg),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_pty_loopback_end_to_end() {
+    let settings = SerialSettings::default();
+    let (read_half, write_half) = PtyLoopback::open("", &settings).await.unwrap().split();
+    let (tx, rx) = mpsc::channel(32);
+    let mut encoder = Encoder::new();
+    let printed = tokio::spawn(printer(rx));
+
+    select! {
+        res = writer(write_half, &mut encoder) => panic!("writer completed with: {res:?}"),
+        res = frame_reader(read_half, tx, settings.timeout) => panic!("reader completed with: {res:?}"),
+        _ = sleep(Duration::from_millis(500)) => {}
+    }
+
+    // Dropping the reader closed the channel, so the printer finishes.
+    let count = timeout(Duration::from_secs(5), printed)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(count >= 2, "printed {count} frames");
+}
+
+#[tokio::test]
+async fn test_pty_loopback_integrity() {
+    let settings = SerialSettings::default();
+    let options = LoopbackOptions {
+        frames: 200,
+        interval: Duration::from_millis(1),
+    };
+    let report = serial_loopback_test("pty://", settings, options)
+        .await
+        .unwrap();
+    assert!(report.passed(), "{report}");
+    assert_eq!(report.received, 200);
+}
@@ -0,0 +1,53 @@
+// Builds the no_std part of the library, crc, frame_buffer and hdlc, for the
+// Cortex-M4F microcontroller on the radar side of the link. The target must
+// be installed with: rustup target add thumbv7em-none-eabihf
+//
+// Without it the test is skipped, unless REQUIRE_THUMBV7EM_BUILD is set, e.g.
+// in CI, to fail instead.
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+const TARGET: &str = "thumbv7em-none-eabihf";
+
+fn target_installed() -> bool {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let output = Command::new(rustc)
+        .args(["--print", "sysroot"])
+        .output()
+        .unwrap();
+    let sysroot = String::from_utf8(output.stdout).unwrap();
+    Path::new(sysroot.trim())
+        .join("lib/rustlib")
+        .join(TARGET)
+        .exists()
+}
+
+#[test]
+fn test_build_no_std() {
+    if !target_installed() {
+        if env::var_os("REQUIRE_THUMBV7EM_BUILD").is_some() {
+            panic!("The {TARGET} target is not installed, add it with rustup");
+        }
+        eprintln!("Skipping, the {TARGET} target is not installed");
+        return;
+    }
+
+    // A separate target directory, so as not to wait on the one running us.
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let status = Command::new(env!("CARGO"))
+        .current_dir(manifest_dir)
+        .args([
+            "build",
+            "--lib",
+            "--no-default-features",
+            "--target",
+            TARGET,
+        ])
+        .arg("--target-dir")
+        .arg(Path::new(manifest_dir).join("target").join(TARGET))
+        .status()
+        .unwrap();
+    assert!(status.success());
+}